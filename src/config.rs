@@ -1,5 +1,7 @@
 use crate::error::{Result, TxGenError};
-use clap::{Parser, ValueEnum};
+use crate::transaction::SOMPI_PER_KAS;
+use crate::wallet::is_extended_key;
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -34,6 +36,48 @@ impl Network {
     }
 }
 
+/// How the spam loop decides when to send
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpamMode {
+    /// Open loop, evenly spaced at the target rate
+    Paced,
+    /// Open loop, exponentially distributed gaps at the target mean rate
+    Poisson,
+    /// Keep a fixed number of submissions outstanding, ignoring the target rate
+    ClosedLoop,
+}
+
+/// When the output of one of our own spam transactions may be spent again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReusePolicy {
+    /// Wait until the node reports the output and it is `confirmation_depth` deep
+    Off,
+    /// As soon as the transaction is signed, before it is submitted
+    Immediate,
+    /// Once the node has accepted the transaction into its mempool
+    Accepted,
+    /// Once the node reports the output in its UTXO set, without waiting for depth
+    Confirmed,
+}
+
+/// Order in which available UTXOs are handed out for spending
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SelectionStrategy {
+    /// Biggest amounts first
+    LargestFirst,
+    /// Smallest amounts first, consolidating dust early
+    SmallestFirst,
+    /// Uniformly random
+    Random,
+    /// Lowest block DAA score first
+    OldestFirst,
+    /// Amount nearest to `selection_target_sompi`
+    Closest,
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
@@ -45,7 +89,7 @@ pub struct Cli {
     #[arg(short, long, value_name = "FILE")]
     pub config: Option<PathBuf>,
 
-    /// Private key (overrides environment variable)
+    /// Private key, raw hex or extended kprv/xprv (overrides environment variable)
     #[arg(short = 'k', long, env = "PRIVATE_KEY_HEX")]
     pub private_key: Option<String>,
 
@@ -64,6 +108,80 @@ pub struct Cli {
     /// Log level (error, warn, info, debug, trace)
     #[arg(short = 'l', long, default_value = "info")]
     pub log_level: String,
+
+    /// Spam mode (paced, poisson or closed-loop)
+    #[arg(short = 'm', long, value_enum)]
+    pub mode: Option<SpamMode>,
+
+    /// Outstanding submissions in closed-loop mode
+    #[arg(long)]
+    pub concurrency: Option<usize>,
+
+    /// UTXO selection strategy (largest-first, smallest-first, random, oldest-first or closest)
+    #[arg(long, value_enum, value_name = "STRATEGY")]
+    pub selection: Option<SelectionStrategy>,
+
+    /// When our own outputs may be spent again (off, immediate, accepted or confirmed)
+    #[arg(long, value_enum, value_name = "POLICY")]
+    pub reuse_outputs: Option<ReusePolicy>,
+
+    /// Embed the run ID and a sequence number in each transaction payload
+    #[arg(long)]
+    pub tag_payloads: bool,
+
+    /// Run ID for payload tagging, up to 16 hex characters (implies --tag-payloads)
+    #[arg(long, value_name = "HEX")]
+    pub run_id: Option<String>,
+
+    /// Journal run state to this file (overrides config file, enables journaling)
+    #[arg(long, value_name = "FILE")]
+    pub state_file: Option<String>,
+
+    /// Resume the run saved in the state journal, if it did not end cleanly
+    #[arg(long, conflicts_with = "fresh")]
+    pub resume: bool,
+
+    /// Ignore any saved state and start a fresh run
+    #[arg(long)]
+    pub fresh: bool,
+
+    /// Start from this UTXO snapshot instead of querying the node
+    #[arg(long, value_name = "FILE")]
+    pub import_snapshot: Option<String>,
+
+    /// Write a UTXO snapshot to this file when the run ends
+    #[arg(long, value_name = "FILE")]
+    pub export_snapshot: Option<String>,
+
+    /// Show the address and wait until this much KAS is spendable before starting
+    #[arg(long, value_name = "KAS")]
+    pub wait_for_funds: Option<f64>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Modes other than the default split-and-spam run
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Send funds from this key to worker generators and wait until they can spend them
+    FundWorkers {
+        /// Worker addresses, comma separated (added to the config file list)
+        #[arg(long, value_delimiter = ',', value_name = "ADDRESS")]
+        workers: Vec<String>,
+
+        /// Worker accounts to derive from the extended private key
+        #[arg(long, value_name = "COUNT")]
+        derive: Option<u32>,
+
+        /// Amount per worker in KAS
+        #[arg(long, value_name = "KAS")]
+        amount: Option<f64>,
+
+        /// Write the per-worker report to this JSON file
+        #[arg(long, value_name = "FILE")]
+        report: Option<String>,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -83,6 +201,21 @@ pub struct Config {
     #[serde(default)]
     pub advanced: AdvancedConfig,
 
+    #[serde(default)]
+    pub state: StateConfig,
+
+    #[serde(default)]
+    pub wallet: WalletConfig,
+
+    #[serde(default)]
+    pub funding: FundingConfig,
+
+    #[serde(default)]
+    pub snapshot: SnapshotConfig,
+
+    #[serde(default)]
+    pub deposit: DepositConfig,
+
     #[serde(default)]
     pub logging: LoggingConfig,
 }
@@ -102,6 +235,14 @@ pub struct UtxoConfig {
     #[serde(default = "default_amount_per_utxo")]
     pub amount_per_utxo: u64,
 
+    /// Mix of UTXO sizes to split into; empty means all at `amount_per_utxo`
+    #[serde(default)]
+    pub denominations: Vec<Denomination>,
+
+    /// Size `target_utxo_count` from the available balance instead
+    #[serde(default)]
+    pub auto_target_count: bool,
+
     #[serde(default = "default_outputs_per_transaction")]
     pub outputs_per_transaction: usize,
 
@@ -110,6 +251,36 @@ pub struct UtxoConfig {
 
     #[serde(default = "default_refresh_interval_secs")]
     pub refresh_interval_secs: u64,
+
+    #[serde(default = "default_use_notifications")]
+    pub use_notifications: bool,
+
+    #[serde(default = "default_reuse_outputs")]
+    pub reuse_outputs: ReusePolicy,
+
+    #[serde(default = "default_selection")]
+    pub selection: SelectionStrategy,
+
+    #[serde(default = "default_selection_target_sompi")]
+    pub selection_target_sompi: u64,
+
+    #[serde(default = "default_replenish")]
+    pub replenish: bool,
+
+    #[serde(default = "default_replenish_below")]
+    pub replenish_below: usize,
+
+    #[serde(default = "default_replenish_cooldown_secs")]
+    pub replenish_cooldown_secs: u64,
+}
+
+/// One UTXO size in a splitting mix
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Denomination {
+    /// Amount of each UTXO in sompi
+    pub amount: u64,
+    /// Share of the target count, relative to the other denominations
+    pub share: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -125,6 +296,29 @@ pub struct SpamConfig {
 
     #[serde(default = "default_millis_per_tick")]
     pub millis_per_tick: u64,
+
+    #[serde(default = "default_tag_payloads")]
+    pub tag_payloads: bool,
+
+    pub run_id: Option<String>,
+
+    #[serde(default = "default_presign_buffer_size")]
+    pub presign_buffer_size: usize,
+
+    #[serde(default = "default_presign_batch_size")]
+    pub presign_batch_size: usize,
+
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+
+    #[serde(default = "default_max_burst")]
+    pub max_burst: u64,
+
+    #[serde(default = "default_spam_mode")]
+    pub mode: SpamMode,
+
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -152,6 +346,102 @@ pub struct AdvancedConfig {
 
     #[serde(default = "default_confirmation_depth")]
     pub confirmation_depth: u64,
+
+    #[serde(default = "default_signing_threads")]
+    pub signing_threads: usize,
+
+    #[serde(default = "default_tokio_worker_threads")]
+    pub tokio_worker_threads: usize,
+
+    #[serde(default = "default_telemetry_interval_ms")]
+    pub telemetry_interval_ms: u64,
+
+    #[serde(default = "default_reconcile_interval_secs")]
+    pub reconcile_interval_secs: u64,
+
+    #[serde(default = "default_reconcile_grace_secs")]
+    pub reconcile_grace_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateConfig {
+    #[serde(default = "default_state_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "default_state_path")]
+    pub path: String,
+
+    #[serde(default = "default_state_resume")]
+    pub resume: bool,
+
+    #[serde(default = "default_state_save_interval_ms")]
+    pub save_interval_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalletConfig {
+    /// Account path below the master key for extended private keys
+    #[serde(default = "default_derivation_path")]
+    pub derivation_path: String,
+
+    #[serde(default = "default_address_count")]
+    pub address_count: u32,
+
+    #[serde(default = "default_gap_limit")]
+    pub gap_limit: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FundingConfig {
+    /// Worker addresses to fund
+    #[serde(default)]
+    pub workers: Vec<String>,
+
+    /// Worker accounts to derive from the extended private key, at m/44'/111111'/<n>'
+    #[serde(default)]
+    pub derived_workers: u32,
+
+    #[serde(default = "default_first_worker_account")]
+    pub first_worker_account: u32,
+
+    #[serde(default = "default_amount_per_worker")]
+    pub amount_per_worker: u64,
+
+    #[serde(default = "default_funding_outputs_per_transaction")]
+    pub outputs_per_transaction: usize,
+
+    #[serde(default = "default_funding_wait_timeout_secs")]
+    pub wait_timeout_secs: u64,
+
+    pub report_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotConfig {
+    pub import_path: Option<String>,
+
+    pub export_path: Option<String>,
+
+    /// Also export every this many seconds while running (0 = only at the end)
+    #[serde(default)]
+    pub export_interval_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DepositConfig {
+    /// Spendable balance to wait for before starting (0 = don't wait)
+    #[serde(default)]
+    pub wait_for_sompi: u64,
+
+    #[serde(default = "default_deposit_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+
+    /// Give up after this many seconds (0 = wait forever)
+    #[serde(default)]
+    pub timeout_secs: u64,
+
+    #[serde(default = "default_show_qr")]
+    pub show_qr: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -175,17 +465,49 @@ fn default_amount_per_utxo() -> u64 { 150_000_000 }
 fn default_outputs_per_transaction() -> usize { 10 }
 fn default_min_change_sompi() -> u64 { 1_000_000 }
 fn default_refresh_interval_secs() -> u64 { 1 }
+fn default_use_notifications() -> bool { true }
+fn default_reuse_outputs() -> ReusePolicy { ReusePolicy::Off }
+fn default_selection() -> SelectionStrategy { SelectionStrategy::LargestFirst }
+fn default_selection_target_sompi() -> u64 { 0 }
+fn default_replenish() -> bool { false }
+fn default_replenish_below() -> usize { 0 }
+fn default_replenish_cooldown_secs() -> u64 { 10 }
 fn default_target_tps() -> u64 { 50 }
 fn default_duration_seconds() -> u64 { 86_400 }
 fn default_unleashed() -> bool { false }
 fn default_millis_per_tick() -> u64 { 10 }
+fn default_tag_payloads() -> bool { false }
+fn default_presign_buffer_size() -> usize { 2_000 }
+fn default_presign_batch_size() -> usize { 250 }
+fn default_workers() -> usize { 1 }
+fn default_max_burst() -> u64 { 0 }
+fn default_spam_mode() -> SpamMode { SpamMode::Paced }
+fn default_concurrency() -> usize { 64 }
 fn default_base_fee_rate() -> u64 { 1 }
 fn default_splitting_fee_rate() -> u64 { 10 }
 fn default_client_pool_size() -> usize { 8 }
 fn default_max_pending_age_secs() -> u64 { 3600 }
 fn default_max_inflight() -> usize { 20_000 }
-fn default_coinbase_maturity() -> u64 { 100 }
+fn default_coinbase_maturity() -> u64 { 1000 }
 fn default_confirmation_depth() -> u64 { 10 }
+fn default_signing_threads() -> usize { 0 }
+fn default_tokio_worker_threads() -> usize { 0 }
+fn default_telemetry_interval_ms() -> u64 { 1_000 }
+fn default_reconcile_interval_secs() -> u64 { 30 }
+fn default_reconcile_grace_secs() -> u64 { 60 }
+fn default_state_enabled() -> bool { false }
+fn default_state_path() -> String { "tx_gen_state.json".to_string() }
+fn default_state_resume() -> bool { false }
+fn default_state_save_interval_ms() -> u64 { 1_000 }
+fn default_derivation_path() -> String { "m/44'/111111'/0'".to_string() }
+fn default_address_count() -> u32 { 10 }
+fn default_gap_limit() -> u32 { 20 }
+fn default_first_worker_account() -> u32 { 1 }
+fn default_amount_per_worker() -> u64 { 10_000_000_000 }
+fn default_funding_outputs_per_transaction() -> usize { 50 }
+fn default_funding_wait_timeout_secs() -> u64 { 600 }
+fn default_deposit_poll_interval_secs() -> u64 { 2 }
+fn default_show_qr() -> bool { true }
 fn default_log_level() -> String { "info".to_string() }
 fn default_colored() -> bool { true }
 fn default_timestamps() -> bool { true }
@@ -205,9 +527,18 @@ impl Default for UtxoConfig {
         Self {
             target_utxo_count: default_target_utxo_count(),
             amount_per_utxo: default_amount_per_utxo(),
+            denominations: Vec::new(),
+            auto_target_count: false,
             outputs_per_transaction: default_outputs_per_transaction(),
             min_change_sompi: default_min_change_sompi(),
             refresh_interval_secs: default_refresh_interval_secs(),
+            use_notifications: default_use_notifications(),
+            reuse_outputs: default_reuse_outputs(),
+            selection: default_selection(),
+            selection_target_sompi: default_selection_target_sompi(),
+            replenish: default_replenish(),
+            replenish_below: default_replenish_below(),
+            replenish_cooldown_secs: default_replenish_cooldown_secs(),
         }
     }
 }
@@ -219,6 +550,14 @@ impl Default for SpamConfig {
             duration_seconds: default_duration_seconds(),
             unleashed: default_unleashed(),
             millis_per_tick: default_millis_per_tick(),
+            tag_payloads: default_tag_payloads(),
+            run_id: None,
+            presign_buffer_size: default_presign_buffer_size(),
+            presign_batch_size: default_presign_batch_size(),
+            workers: default_workers(),
+            max_burst: default_max_burst(),
+            mode: default_spam_mode(),
+            concurrency: default_concurrency(),
         }
     }
 }
@@ -240,6 +579,67 @@ impl Default for AdvancedConfig {
             max_inflight: default_max_inflight(),
             coinbase_maturity: default_coinbase_maturity(),
             confirmation_depth: default_confirmation_depth(),
+            signing_threads: default_signing_threads(),
+            tokio_worker_threads: default_tokio_worker_threads(),
+            telemetry_interval_ms: default_telemetry_interval_ms(),
+            reconcile_interval_secs: default_reconcile_interval_secs(),
+            reconcile_grace_secs: default_reconcile_grace_secs(),
+        }
+    }
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_state_enabled(),
+            path: default_state_path(),
+            resume: default_state_resume(),
+            save_interval_ms: default_state_save_interval_ms(),
+        }
+    }
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        Self {
+            derivation_path: default_derivation_path(),
+            address_count: default_address_count(),
+            gap_limit: default_gap_limit(),
+        }
+    }
+}
+
+impl Default for FundingConfig {
+    fn default() -> Self {
+        Self {
+            workers: Vec::new(),
+            derived_workers: 0,
+            first_worker_account: default_first_worker_account(),
+            amount_per_worker: default_amount_per_worker(),
+            outputs_per_transaction: default_funding_outputs_per_transaction(),
+            wait_timeout_secs: default_funding_wait_timeout_secs(),
+            report_path: None,
+        }
+    }
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            import_path: None,
+            export_path: None,
+            export_interval_secs: 0,
+        }
+    }
+}
+
+impl Default for DepositConfig {
+    fn default() -> Self {
+        Self {
+            wait_for_sompi: 0,
+            poll_interval_secs: default_deposit_poll_interval_secs(),
+            timeout_secs: 0,
+            show_qr: default_show_qr(),
         }
     }
 }
@@ -263,6 +663,11 @@ impl Default for Config {
             spam: SpamConfig::default(),
             fees: FeeConfig::default(),
             advanced: AdvancedConfig::default(),
+            state: StateConfig::default(),
+            wallet: WalletConfig::default(),
+            funding: FundingConfig::default(),
+            snapshot: SnapshotConfig::default(),
+            deposit: DepositConfig::default(),
             logging: LoggingConfig::default(),
         }
     }
@@ -302,8 +707,105 @@ pub fn load_config(cli: &Cli) -> Result<(Config, String)> {
         config.spam.duration_seconds = duration;
     }
 
+    if let Some(mode) = cli.mode {
+        config.spam.mode = mode;
+    }
+
+    if let Some(concurrency) = cli.concurrency {
+        config.spam.concurrency = concurrency;
+    }
+
+    if let Some(selection) = cli.selection {
+        config.utxo.selection = selection;
+    }
+
+    if let Some(policy) = cli.reuse_outputs {
+        config.utxo.reuse_outputs = policy;
+    }
+
+    if config.utxo.denominations.iter().any(|d| d.amount == 0 || d.share == 0) {
+        return Err(TxGenError::Config(
+            "Every denomination needs a positive amount and share".to_string()
+        ));
+    }
+
+    if config.spam.mode == SpamMode::ClosedLoop && !config.spam.unleashed {
+        return Err(TxGenError::Config(
+            "Closed-loop mode is not rate limited; set unleashed = true to use it".to_string()
+        ));
+    }
+
+    if config.spam.mode == SpamMode::ClosedLoop && config.spam.concurrency < config.spam.workers.max(1) {
+        return Err(TxGenError::Config(format!(
+            "Closed-loop concurrency {} is below the worker count {}; every worker needs a slot",
+            config.spam.concurrency, config.spam.workers
+        )));
+    }
+
     config.logging.level = cli.log_level.clone();
 
+    if cli.tag_payloads {
+        config.spam.tag_payloads = true;
+    }
+
+    if let Some(run_id) = &cli.run_id {
+        config.spam.run_id = Some(run_id.clone());
+        config.spam.tag_payloads = true;
+    }
+
+    if let Some(run_id) = &config.spam.run_id {
+        if run_id.is_empty() || run_id.len() > 16 || u64::from_str_radix(run_id, 16).is_err() {
+            return Err(TxGenError::Config(
+                "Run ID must be 1 to 16 hexadecimal characters".to_string()
+            ));
+        }
+    }
+
+    if let Some(path) = &cli.state_file {
+        config.state.path = path.clone();
+        config.state.enabled = true;
+    }
+
+    if cli.resume {
+        config.state.enabled = true;
+        config.state.resume = true;
+    }
+
+    if cli.fresh {
+        config.state.resume = false;
+    }
+
+    if let Some(path) = &cli.import_snapshot {
+        config.snapshot.import_path = Some(path.clone());
+    }
+
+    if let Some(path) = &cli.export_snapshot {
+        config.snapshot.export_path = Some(path.clone());
+    }
+
+    if let Some(kas) = cli.wait_for_funds {
+        if kas <= 0.0 {
+            return Err(TxGenError::Config("Balance to wait for must be positive".to_string()));
+        }
+        config.deposit.wait_for_sompi = (kas * SOMPI_PER_KAS).round() as u64;
+    }
+
+    if let Some(Command::FundWorkers { workers, derive, amount, report }) = &cli.command {
+        config.funding.workers.extend(workers.iter().cloned());
+        if let Some(count) = derive {
+            config.funding.derived_workers = *count;
+        }
+        if let Some(kas) = amount {
+            if *kas <= 0.0 {
+                return Err(TxGenError::Config("Funding amount must be positive".to_string()));
+            }
+            config.funding.amount_per_worker = (kas * SOMPI_PER_KAS).round() as u64;
+        }
+        if let Some(path) = report {
+            config.funding.report_path = Some(path.clone());
+        }
+    }
+
     // Get private key from CLI, env, or error
     let private_key = cli.private_key.clone()
         .or_else(|| std::env::var("PRIVATE_KEY_HEX").ok())
@@ -311,10 +813,10 @@ pub fn load_config(cli: &Cli) -> Result<(Config, String)> {
             "Private key not provided. Set PRIVATE_KEY_HEX environment variable or use --private-key".to_string()
         ))?;
 
-    // Validate private key format
-    if private_key.len() != 64 || hex::decode(&private_key).is_err() {
+    // Validate private key format; extended keys are checked when the wallet is derived
+    if !is_extended_key(&private_key) && (private_key.len() != 64 || hex::decode(&private_key).is_err()) {
         return Err(TxGenError::InvalidPrivateKey(
-            "Private key must be 64 hexadecimal characters".to_string()
+            "Private key must be 64 hexadecimal characters or an extended private key (kprv/xprv)".to_string()
         ));
     }
 
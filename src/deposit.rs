@@ -0,0 +1,199 @@
+use crate::config::Config;
+use crate::error::{Result, TxGenError};
+use crate::notifications::UtxoSubscription;
+use crate::transaction::SOMPI_PER_KAS;
+use crate::utxo::{fetch_utxo_set, UtxoSet};
+use crate::wallet::Wallet;
+use kaspa_addresses::Address;
+use kaspa_consensus_core::tx::TransactionOutpoint;
+use kaspa_grpc_client::GrpcClient;
+use kaspa_rpc_core::{api::rpc::RpcApi, model::GetServerInfoRequest};
+use qrcode::{render::unicode, QrCode};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::time::{interval, Instant, MissedTickBehavior};
+use tracing::{info, warn};
+
+/// What the wallet holds while waiting for a deposit
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Balance {
+    spendable: u64,
+    confirming: u64,
+    confirming_count: usize,
+    /// DAA scores until the next confirming UTXO becomes spendable
+    next_in: Option<u64>,
+}
+
+/// Blocks until `deposit.wait_for_sompi` is spendable, showing the deposit address and
+/// each incoming UTXO as it confirms. Watches UtxosChanged notifications when enabled and
+/// polls every `poll_interval_secs` either way. Returns whether it had to wait.
+pub async fn wait_for_funds(client: &GrpcClient, wallet: &Wallet, config: &Config) -> Result<bool> {
+    let threshold = config.deposit.wait_for_sompi;
+    let addresses = wallet.addresses();
+
+    // UTXOs already there are not announced as deposits
+    let mut seen = HashSet::new();
+    let mut balance = check(client, &addresses, config, &mut seen, false).await?;
+    if balance.spendable >= threshold {
+        return Ok(false);
+    }
+
+    show_address(&wallet.primary().address, threshold, config);
+    log_balance(&balance, threshold);
+
+    let subscription = if config.utxo.use_notifications {
+        match UtxoSubscription::connect(config, addresses.clone()).await {
+            Ok(subscription) => Some(subscription),
+            Err(e) => {
+                warn!("Watching for deposits by polling only: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let timeout = config.deposit.timeout_secs;
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    let mut ticker = interval(Duration::from_secs(config.deposit.poll_interval_secs.max(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            Some(_) = next_deposit(subscription.as_ref()) => {}
+        }
+
+        let current = check(client, &addresses, config, &mut seen, true).await?;
+        if current.spendable >= threshold {
+            info!(
+                "Balance of {:.2} KAS is spendable, starting",
+                current.spendable as f64 / SOMPI_PER_KAS
+            );
+            return Ok(true);
+        }
+        if current != balance {
+            log_balance(&current, threshold);
+            balance = current;
+        }
+
+        if timeout > 0 && Instant::now() >= deadline {
+            return Err(TxGenError::InsufficientFunds {
+                required: threshold as f64 / SOMPI_PER_KAS,
+                available: current.spendable as f64 / SOMPI_PER_KAS,
+            });
+        }
+    }
+}
+
+/// Prints the deposit address, with a QR code for scanning it from a wallet app
+fn show_address(address: &Address, threshold: u64, config: &Config) {
+    info!(
+        "Waiting for {:.2} KAS to be spendable. Send funds to: {}",
+        threshold as f64 / SOMPI_PER_KAS,
+        address
+    );
+
+    if !config.deposit.show_qr {
+        return;
+    }
+    match QrCode::new(address.to_string().as_bytes()) {
+        // Printed directly, since log prefixes would break the code up
+        Ok(code) => println!(
+            "{}",
+            code.render::<unicode::Dense1x2>()
+                .dark_color(unicode::Dense1x2::Light)
+                .light_color(unicode::Dense1x2::Dark)
+                .build()
+        ),
+        Err(e) => warn!("Failed to render QR code: {}", e),
+    }
+}
+
+/// Fetches the balance, announcing UTXOs not seen before when `announce` is set
+async fn check(
+    client: &GrpcClient,
+    addresses: &[Address],
+    config: &Config,
+    seen: &mut HashSet<TransactionOutpoint>,
+    announce: bool,
+) -> Result<Balance> {
+    let set = fetch_utxo_set(client, addresses, config).await?;
+    let virtual_daa_score = client
+        .get_server_info_call(None, GetServerInfoRequest {})
+        .await?
+        .virtual_daa_score;
+
+    Ok(tally(&set, virtual_daa_score, seen, announce))
+}
+
+/// Sums the balance of `set` at `virtual_daa_score`, announcing UTXOs not in `seen` when `announce` is set
+fn tally(set: &UtxoSet, virtual_daa_score: u64, seen: &mut HashSet<TransactionOutpoint>, announce: bool) -> Balance {
+    let mut balance = Balance::default();
+    for (outpoint, entry) in &set.spendable {
+        balance.spendable += entry.amount;
+        if seen.insert(*outpoint) && announce {
+            info!("Received {:.8} KAS in {}, spendable", entry.amount as f64 / SOMPI_PER_KAS, outpoint);
+        }
+    }
+    for (outpoint, entry, ready_at) in &set.immature {
+        balance.confirming += entry.amount;
+        balance.confirming_count += 1;
+
+        let remaining = ready_at.saturating_sub(virtual_daa_score);
+        balance.next_in = Some(balance.next_in.map_or(remaining, |next| next.min(remaining)));
+
+        if seen.insert(*outpoint) && announce {
+            info!(
+                "Received {:.8} KAS in {} ({}/{} confirmations)",
+                entry.amount as f64 / SOMPI_PER_KAS,
+                outpoint,
+                virtual_daa_score.saturating_sub(entry.block_daa_score),
+                ready_at - entry.block_daa_score
+            );
+        }
+    }
+
+    balance
+}
+
+fn log_balance(balance: &Balance, threshold: u64) {
+    let next = balance.next_in.map_or_else(|| "-".to_string(), |daa| format!("{} DAA", daa));
+    info!(
+        "Balance: {:.2} KAS spendable, {:.2} KAS in {} UTXOs confirming (next spendable in {}) | need {:.2} KAS",
+        balance.spendable as f64 / SOMPI_PER_KAS,
+        balance.confirming as f64 / SOMPI_PER_KAS,
+        balance.confirming_count,
+        next,
+        threshold as f64 / SOMPI_PER_KAS
+    );
+}
+
+async fn next_deposit(subscription: Option<&UtxoSubscription>) -> Option<()> {
+    match subscription {
+        Some(subscription) => subscription.recv().await.map(|_| ()),
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{outpoint, utxo};
+
+    #[test]
+    fn balance_counts_confirming_utxos_until_they_are_spendable() {
+        let set = UtxoSet {
+            spendable: vec![utxo(1, 300)],
+            immature: vec![(outpoint(2), utxo(2, 200).1, 120), (outpoint(3), utxo(3, 100).1, 105)],
+            ..Default::default()
+        };
+        let mut seen = HashSet::new();
+
+        let balance = tally(&set, 100, &mut seen, false);
+        assert_eq!(balance, Balance { spendable: 300, confirming: 300, confirming_count: 2, next_in: Some(5) });
+        assert_eq!(seen.len(), 3);
+        assert_eq!(tally(&set, 110, &mut seen, true).next_in, Some(0));
+    }
+}
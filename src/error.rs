@@ -47,6 +47,9 @@ pub enum TxGenError {
     #[error("UTXO management error: {0}")]
     UtxoManagement(String),
 
+    #[error("Signing error: {0}")]
+    Signing(String),
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
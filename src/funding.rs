@@ -0,0 +1,448 @@
+use crate::config::Config;
+use crate::error::{Result, TxGenError};
+use crate::transaction::{create_funding_transaction, transaction_mass, SOMPI_PER_KAS};
+use crate::utxo::{fetch_spendable_utxos, spendable_at};
+use crate::wallet::Wallet;
+use kaspa_addresses::Address;
+use kaspa_consensus_core::tx::{
+    ScriptPublicKey, Transaction, TransactionId, TransactionOutpoint, UtxoEntry as CoreUtxoEntry,
+};
+use kaspa_grpc_client::GrpcClient;
+use kaspa_rpc_core::{
+    api::rpc::RpcApi,
+    model::{GetServerInfoRequest, GetUtxosByAddressesRequest, SubmitTransactionRequest},
+    RpcTransaction,
+};
+use kaspa_txscript::pay_to_address_script;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{info, warn};
+
+/// Most inputs a single funding transaction spends
+const MAX_FUNDING_INPUTS: usize = 64;
+
+type Utxo = (TransactionOutpoint, CoreUtxoEntry);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FundingStatus {
+    /// Holds at least the configured amount in spendable UTXOs
+    Funded,
+    /// Funding transaction accepted, waiting for confirmations
+    Pending,
+    /// No funding transaction could be sent
+    Failed,
+}
+
+impl FundingStatus {
+    fn label(self) -> &'static str {
+        match self {
+            FundingStatus::Funded => "funded",
+            FundingStatus::Pending => "pending",
+            FundingStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Funding outcome for one worker
+#[derive(Debug, Serialize)]
+pub struct WorkerReport {
+    pub address: String,
+    /// Account path of a worker derived from the master key
+    pub path: Option<String>,
+    pub funding_txid: Option<TransactionId>,
+    pub spendable_sompi: u64,
+    pub pending_sompi: u64,
+    pub status: FundingStatus,
+}
+
+struct Worker {
+    address: Address,
+    path: Option<String>,
+}
+
+/// A signed funding transaction with the inputs it spends
+struct Funding {
+    script: ScriptPublicKey,
+    inputs: Vec<Utxo>,
+    tx: Transaction,
+    change: Option<u64>,
+}
+
+/// Sends `amount_per_worker` from the wallet to every worker in batched transactions,
+/// waits until each worker can spend it and reports the outcome per worker
+pub async fn fund_workers(clients: &[Arc<GrpcClient>], wallet: &Wallet, config: &Config) -> Result<()> {
+    info!("=== Funding Workers ===");
+
+    let workers = worker_targets(wallet, config)?;
+    info!(
+        "Funding {} workers with {:.4} KAS each, {} per transaction",
+        workers.len(),
+        config.funding.amount_per_worker as f64 / SOMPI_PER_KAS,
+        config.funding.outputs_per_transaction.max(1)
+    );
+
+    let txids = distribute(clients, wallet, &workers, config).await?;
+    let mut reports: Vec<WorkerReport> = workers
+        .into_iter()
+        .zip(txids)
+        .map(|(worker, txid)| WorkerReport {
+            address: worker.address.to_string(),
+            path: worker.path,
+            funding_txid: txid,
+            spendable_sompi: 0,
+            pending_sompi: 0,
+            status: if txid.is_some() { FundingStatus::Pending } else { FundingStatus::Failed },
+        })
+        .collect();
+
+    wait_for_workers(&clients[0], &mut reports, config).await?;
+    log_report(&reports);
+
+    if let Some(path) = &config.funding.report_path {
+        let data = serde_json::to_vec_pretty(&reports)
+            .map_err(|e| TxGenError::Parse(format!("Failed to encode funding report: {}", e)))?;
+        std::fs::write(path, data)?;
+        info!("Wrote funding report to {}", path);
+    }
+
+    let unfunded = reports.iter().filter(|r| r.status != FundingStatus::Funded).count();
+    if unfunded > 0 {
+        return Err(TxGenError::UtxoManagement(format!(
+            "{} of {} workers were not funded",
+            unfunded,
+            reports.len()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Configured worker addresses followed by derived worker accounts
+fn worker_targets(wallet: &Wallet, config: &Config) -> Result<Vec<Worker>> {
+    let prefix = config.network.network.prefix();
+    let mut workers = Vec::new();
+
+    for text in &config.funding.workers {
+        let address = Address::try_from(text.as_str())
+            .map_err(|e| TxGenError::Config(format!("Invalid worker address {}: {}", text, e)))?;
+        if address.prefix != prefix {
+            return Err(TxGenError::NetworkMismatch {
+                address_prefix: format!("{:?}", address.prefix),
+                network: format!("{:?}", config.network.network),
+            });
+        }
+        workers.push(Worker { address, path: None });
+    }
+
+    for i in 0..config.funding.derived_workers {
+        let (address, path) = wallet.account_address(config.funding.first_worker_account + i)?;
+        workers.push(Worker { address, path: Some(path) });
+    }
+
+    if workers.is_empty() {
+        return Err(TxGenError::Config(
+            "No workers to fund; list addresses with --workers or derive them with --derive".to_string()
+        ));
+    }
+
+    Ok(workers)
+}
+
+/// Submits one funding transaction per batch of workers. Each transaction spends UTXOs
+/// of a single wallet key and returns its change there, so later batches can chain on it.
+async fn distribute(
+    clients: &[Arc<GrpcClient>],
+    wallet: &Wallet,
+    workers: &[Worker],
+    config: &Config,
+) -> Result<Vec<Option<TransactionId>>> {
+    let amount = config.funding.amount_per_worker;
+    let per_tx = config.funding.outputs_per_transaction.max(1);
+
+    let utxos = fetch_spendable_utxos(&clients[0], &wallet.addresses(), config).await?;
+    let available: u64 = utxos.iter().map(|(_, entry)| entry.amount).sum();
+
+    // Fees depend on the inputs each batch ends up spending and are covered per batch
+    let required = amount * workers.len() as u64;
+    if available < required {
+        return Err(TxGenError::InsufficientFunds {
+            required: required as f64 / SOMPI_PER_KAS,
+            available: available as f64 / SOMPI_PER_KAS,
+        });
+    }
+
+    // Smallest first within each key, so popping takes the largest
+    let mut groups: HashMap<ScriptPublicKey, Vec<Utxo>> = HashMap::new();
+    for utxo in utxos {
+        groups.entry(utxo.1.script_public_key.clone()).or_default().push(utxo);
+    }
+    for group in groups.values_mut() {
+        group.sort_by_key(|(_, entry)| entry.amount);
+    }
+
+    let mut txids = vec![None; workers.len()];
+    for (batch_index, batch) in workers.chunks(per_tx).enumerate() {
+        let first = batch_index * per_tx;
+        let Some(Funding { script, inputs, tx, change }) = build_funding(&mut groups, wallet, batch, config)? else {
+            warn!(
+                "No single key holds {:.4} KAS plus fees in {} UTXOs or fewer, {} workers left unfunded",
+                (amount * batch.len() as u64) as f64 / SOMPI_PER_KAS,
+                MAX_FUNDING_INPUTS,
+                workers.len() - first
+            );
+            break;
+        };
+
+        let client = &clients[batch_index % clients.len()];
+        let result = client
+            .submit_transaction_call(None, SubmitTransactionRequest {
+                transaction: RpcTransaction::from(&tx),
+                allow_orphan: false,
+            })
+            .await;
+
+        let group = groups.get_mut(&script).expect("inputs were taken from this group");
+        match result {
+            Ok(_) => {
+                let txid = tx.id();
+                info!("Funding transaction {} pays workers {}..{}", txid, first + 1, first + batch.len());
+                txids[first..first + batch.len()].fill(Some(txid));
+
+                // The change is spendable by the next batch while still in the mempool
+                if let Some(change) = change {
+                    let change_utxo = (
+                        TransactionOutpoint::new(txid, batch.len() as u32),
+                        CoreUtxoEntry::new(change, script, 0, false),
+                    );
+                    let at = group.partition_point(|(_, entry)| entry.amount < change);
+                    group.insert(at, change_utxo);
+                }
+            }
+            Err(e) => {
+                warn!("Funding transaction for workers {}..{} failed: {}", first + 1, first + batch.len(), e);
+                put_back(group, inputs);
+            }
+        }
+    }
+
+    Ok(txids)
+}
+
+/// Builds the transaction paying `batch` from UTXOs of a single key, charging the splitting
+/// fee rate on its mass. The mass depends on how many inputs cover the payout plus fee and
+/// whether there is change, so it is rebuilt until the fee it carries covers its own mass.
+/// Returns `None` if no key can pay for the batch.
+fn build_funding(
+    groups: &mut HashMap<ScriptPublicKey, Vec<Utxo>>,
+    wallet: &Wallet,
+    batch: &[Worker],
+    config: &Config,
+) -> Result<Option<Funding>> {
+    let amount = config.funding.amount_per_worker;
+    let payout = amount * batch.len() as u64;
+    let mut fee = 0;
+
+    loop {
+        let Some((script, inputs)) = groups
+            .iter_mut()
+            .find_map(|(script, group)| take_inputs(group, payout + fee).map(|inputs| (script.clone(), inputs)))
+        else {
+            return Ok(None);
+        };
+        let key = wallet
+            .key_for(&script)
+            .ok_or_else(|| TxGenError::Signing("No wallet key for funding inputs".to_string()))?;
+
+        let mut outputs: Vec<(u64, ScriptPublicKey)> =
+            batch.iter().map(|worker| (amount, pay_to_address_script(&worker.address))).collect();
+        let change = inputs.iter().map(|(_, entry)| entry.amount).sum::<u64>() - payout - fee;
+        let change = (change >= config.utxo.min_change_sompi).then_some(change);
+        if let Some(change) = change {
+            outputs.push((change, script.clone()));
+        }
+
+        let tx = create_funding_transaction(&key.keypair, &inputs, &outputs)?;
+        let required = config.fees.splitting_fee_rate * transaction_mass(&tx);
+        if required <= fee {
+            return Ok(Some(Funding { script, inputs, tx, change }));
+        }
+
+        put_back(groups.get_mut(&script).expect("inputs were taken from this group"), inputs);
+        fee = required;
+    }
+}
+
+/// Returns unspent inputs to their group, keeping it sorted by amount
+fn put_back(group: &mut Vec<Utxo>, inputs: Vec<Utxo>) {
+    for input in inputs {
+        let at = group.partition_point(|(_, entry)| entry.amount < input.1.amount);
+        group.insert(at, input);
+    }
+}
+
+/// Takes the largest UTXOs of a group until they cover `need`, or nothing if they cannot
+fn take_inputs(group: &mut Vec<Utxo>, need: u64) -> Option<Vec<Utxo>> {
+    let mut inputs = Vec::new();
+    let mut total = 0u64;
+    while total < need && inputs.len() < MAX_FUNDING_INPUTS {
+        let Some(utxo) = group.pop() else {
+            break;
+        };
+        total += utxo.1.amount;
+        inputs.push(utxo);
+    }
+
+    if total >= need {
+        return Some(inputs);
+    }
+    // Put them back, largest last as before
+    group.extend(inputs.into_iter().rev());
+    None
+}
+
+/// Polls worker balances until every funded worker can spend its amount or the timeout passes
+async fn wait_for_workers(client: &GrpcClient, reports: &mut [WorkerReport], config: &Config) -> Result<()> {
+    let amount = config.funding.amount_per_worker;
+    let deadline = Instant::now() + Duration::from_secs(config.funding.wait_timeout_secs);
+    let index: HashMap<String, usize> = reports.iter().enumerate().map(|(i, r)| (r.address.clone(), i)).collect();
+    let addresses: Vec<Address> = reports
+        .iter()
+        .filter_map(|r| Address::try_from(r.address.as_str()).ok())
+        .collect();
+
+    let mut ticker = interval(Duration::from_secs(2));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        ticker.tick().await;
+
+        let resp = client
+            .get_utxos_by_addresses_call(None, GetUtxosByAddressesRequest { addresses: addresses.clone() })
+            .await?;
+        let virtual_daa_score = client
+            .get_server_info_call(None, GetServerInfoRequest {})
+            .await?
+            .virtual_daa_score;
+
+        for report in reports.iter_mut() {
+            report.spendable_sompi = 0;
+            report.pending_sompi = 0;
+        }
+        for entry in resp.entries {
+            let Some(&i) = entry.address.as_ref().and_then(|a| index.get(&a.to_string())) else {
+                continue;
+            };
+            let utxo_entry = CoreUtxoEntry::from(entry.utxo_entry);
+            if spendable_at(&utxo_entry, config) <= virtual_daa_score {
+                reports[i].spendable_sompi += utxo_entry.amount;
+            } else {
+                reports[i].pending_sompi += utxo_entry.amount;
+            }
+        }
+
+        for report in reports.iter_mut() {
+            if report.status == FundingStatus::Pending && report.spendable_sompi >= amount {
+                report.status = FundingStatus::Funded;
+            }
+        }
+
+        let waiting = reports.iter().filter(|r| r.status == FundingStatus::Pending).count();
+        if waiting == 0 {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            warn!("Timed out with {} workers still waiting for confirmations", waiting);
+            return Ok(());
+        }
+        info!(
+            "Waiting for {} of {} workers to have a spendable balance",
+            waiting,
+            reports.len()
+        );
+    }
+}
+
+fn log_report(reports: &[WorkerReport]) {
+    info!("=== Funding Report ===");
+    for (i, report) in reports.iter().enumerate() {
+        info!(
+            "Worker {:>3} {:<8} {} | spendable: {:.4} KAS | pending: {:.4} KAS | tx: {}{}",
+            i + 1,
+            report.status.label(),
+            report.address,
+            report.spendable_sompi as f64 / SOMPI_PER_KAS,
+            report.pending_sompi as f64 / SOMPI_PER_KAS,
+            report.funding_txid.map(|id| id.to_string()).unwrap_or_else(|| "-".to_string()),
+            report.path.as_ref().map(|path| format!(" | path: {}", path)).unwrap_or_default()
+        );
+    }
+
+    let funded = reports.iter().filter(|r| r.status == FundingStatus::Funded).count();
+    info!("Funded {} of {} workers", funded, reports.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{outpoint, wallet};
+    use kaspa_addresses::Version;
+
+    const AMOUNT: u64 = 100_000_000;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.funding.amount_per_worker = AMOUNT;
+        config
+    }
+
+    fn workers(wallet: &Wallet, count: u8) -> Vec<Worker> {
+        let prefix = wallet.primary().address.prefix;
+        (1..=count)
+            .map(|n| Worker { address: Address::new(prefix, Version::PubKey, &[n; 32]), path: None })
+            .collect()
+    }
+
+    /// The wallet key's UTXOs of the given amounts, sorted as `distribute` keeps them
+    fn groups(wallet: &Wallet, amounts: &[u64]) -> HashMap<ScriptPublicKey, Vec<Utxo>> {
+        let script = wallet.primary().script_public_key.clone();
+        let mut group: Vec<Utxo> = amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| (outpoint(i as u8 + 1), CoreUtxoEntry::new(amount, script.clone(), 0, false)))
+            .collect();
+        group.sort_by_key(|(_, entry)| entry.amount);
+        HashMap::from([(script, group)])
+    }
+
+    #[test]
+    fn funding_fee_covers_the_mass_of_the_final_transaction() {
+        let (wallet, config) = (wallet(), config());
+        let batch = workers(&wallet, 2);
+        // The first input covers the payout alone, but not the fee on top of it
+        let mut groups = groups(&wallet, &[2 * AMOUNT, 10_000_000]);
+
+        let funding = build_funding(&mut groups, &wallet, &batch, &config).unwrap().expect("wallet can pay");
+        let paid: u64 = funding.tx.outputs.iter().map(|output| output.value).sum();
+        let fee = 2 * AMOUNT + 10_000_000 - paid;
+
+        assert_eq!(funding.inputs.len(), 2);
+        assert_eq!(funding.tx.outputs.len(), 3);
+        assert_eq!(funding.change, Some(funding.tx.outputs[2].value));
+        assert!(fee >= config.fees.splitting_fee_rate * transaction_mass(&funding.tx));
+        assert!(groups.values().all(|group| group.is_empty()));
+    }
+
+    #[test]
+    fn funding_leaves_the_inputs_when_no_key_can_pay() {
+        let (wallet, config) = (wallet(), config());
+        let batch = workers(&wallet, 3);
+        let mut groups = groups(&wallet, &[AMOUNT, AMOUNT, AMOUNT]);
+
+        assert!(build_funding(&mut groups, &wallet, &batch, &config).unwrap().is_none());
+        assert_eq!(groups.values().map(Vec::len).sum::<usize>(), 3);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::error::{Result, TxGenError};
+use crate::stats::SpamStats;
+use crate::utxo::{fetch_mempool_spends, lock_shard, UtxoSet, UtxoShard};
+use kaspa_addresses::Address;
+use kaspa_consensus_core::tx::{TransactionId, TransactionOutpoint, UtxoEntry as CoreUtxoEntry};
+use kaspa_grpc_client::GrpcClient;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, warn};
+
+const STATE_VERSION: u32 = 1;
+
+/// An outpoint we spent in a transaction that may still be in the mempool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingSpend {
+    pub outpoint: TransactionOutpoint,
+    pub txid: Option<TransactionId>,
+}
+
+/// Everything a restarted run needs to carry on where the last one stopped
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunState {
+    pub version: u32,
+    pub address: String,
+    /// Unix time of the save, in seconds
+    pub saved_at: u64,
+    pub run_id: Option<u64>,
+    pub next_seq: u64,
+    pub sent: u64,
+    pub failed: u64,
+    pub runtime_secs: u64,
+    pub pending: Vec<PendingSpend>,
+    /// Set by a clean shutdown. Such a run is not resumed, only its pending spends are honoured.
+    #[serde(default)]
+    pub completed: bool,
+}
+
+impl RunState {
+    /// Reads a saved state. Returns `None` if there is none or it belongs to another address.
+    pub fn load(path: &Path, address: &Address) -> Result<Option<Self>> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let state: RunState = serde_json::from_slice(&data)
+            .map_err(|e| TxGenError::Parse(format!("Invalid state file {}: {}", path.display(), e)))?;
+
+        if state.version != STATE_VERSION {
+            warn!("State file {} has unsupported version {}, ignoring it", path.display(), state.version);
+            return Ok(None);
+        }
+        if state.address != address.to_string() {
+            warn!("State file {} belongs to {}, ignoring it", path.display(), state.address);
+            return Ok(None);
+        }
+
+        Ok(Some(state))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_vec(self)
+            .map_err(|e| TxGenError::Parse(format!("Failed to encode state: {}", e)))?;
+        write_atomic(path, &data)
+    }
+
+    pub fn age_secs(&self) -> u64 {
+        unix_now().saturating_sub(self.saved_at)
+    }
+}
+
+/// How the pending spends of a resumed run were resolved against the node
+#[derive(Debug, Default)]
+pub struct ResumeSummary {
+    /// Journaled spends still in the mempool
+    pub in_mempool: usize,
+    /// Spends missing from the journal (saved too late) but found in the mempool
+    pub untracked: usize,
+    /// Journaled outpoints the node no longer reports, so the spend was confirmed
+    pub confirmed: usize,
+    /// Journaled outpoints still unspent with no spend in the mempool
+    pub lost: usize,
+}
+
+/// Checks a fetched UTXO set against the mempool and a previous run's pending spends.
+/// Outpoints spent in the mempool are taken out of `set` and returned so they stay
+/// locked; lost spends stay in `set` and are reused.
+pub async fn reconcile(
+    client: &GrpcClient,
+    addresses: &[Address],
+    state: &RunState,
+    set: &mut UtxoSet,
+) -> Result<(Vec<(TransactionOutpoint, CoreUtxoEntry, Option<TransactionId>)>, ResumeSummary)> {
+    let mempool = fetch_mempool_spends(client, addresses.to_vec()).await?;
+    let journaled: HashMap<_, _> = state.pending.iter().map(|p| (p.outpoint, p.txid)).collect();
+
+    let mut summary = ResumeSummary::default();
+    let mut locked = Vec::new();
+
+    set.spendable.retain(|(op, entry)| {
+        let journaled_txid = journaled.get(op);
+        match (mempool.get(op), journaled_txid) {
+            (Some(&spender), Some(&txid)) => {
+                summary.in_mempool += 1;
+                locked.push((*op, entry.clone(), spender.or(txid)));
+                false
+            }
+            (Some(&spender), None) => {
+                summary.untracked += 1;
+                locked.push((*op, entry.clone(), spender));
+                false
+            }
+            (None, Some(_)) => {
+                summary.lost += 1;
+                true
+            }
+            (None, None) => true,
+        }
+    });
+
+    summary.confirmed = journaled.len() - summary.in_mempool - summary.lost;
+    Ok((locked, summary))
+}
+
+/// Saves pool and run state so a restarted run can resume without double spending
+pub struct Journal {
+    path: PathBuf,
+    address: String,
+    run_id: Option<u64>,
+    shards: Vec<UtxoShard>,
+    stats: Arc<SpamStats>,
+    next_seq: Arc<AtomicU64>,
+    started: Instant,
+    runtime_offset: Duration,
+    // Keeps the periodic and final saves from writing the temporary file at once
+    save_lock: Mutex<()>,
+    // Set by the final save, after which a late periodic one must not undo it
+    finished: AtomicBool,
+}
+
+impl Journal {
+    pub fn new(
+        path: PathBuf,
+        address: &Address,
+        run_id: Option<u64>,
+        shards: Vec<UtxoShard>,
+        stats: Arc<SpamStats>,
+        next_seq: Arc<AtomicU64>,
+        runtime_offset: Duration,
+    ) -> Self {
+        Self {
+            path,
+            address: address.to_string(),
+            run_id,
+            shards,
+            stats,
+            next_seq,
+            started: Instant::now(),
+            runtime_offset,
+            save_lock: Mutex::new(()),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let _guard = self.save_lock.lock().unwrap_or_else(|e| e.into_inner());
+        if self.finished.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.capture(false).save(&self.path)
+    }
+
+    /// Final save on a clean shutdown, marking the run completed so it is not resumed
+    pub fn finish(&self) -> Result<()> {
+        let _guard = self.save_lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.finished.store(true, Ordering::Relaxed);
+        self.capture(true).save(&self.path)
+    }
+
+    fn capture(&self, completed: bool) -> RunState {
+        let mut pending = Vec::new();
+        for shard in &self.shards {
+            pending.extend(
+                lock_shard(shard)
+                    .pending_spends()
+                    .map(|(outpoint, txid)| PendingSpend { outpoint, txid }),
+            );
+        }
+
+        let (sent, failed) = self.stats.totals();
+        RunState {
+            version: STATE_VERSION,
+            address: self.address.clone(),
+            saved_at: unix_now(),
+            run_id: self.run_id,
+            next_seq: self.next_seq.load(Ordering::Relaxed),
+            sent,
+            failed,
+            runtime_secs: (self.started.elapsed() + self.runtime_offset).as_secs(),
+            pending,
+            completed,
+        }
+    }
+}
+
+/// Saves the journal every `every` off the async workers
+pub fn spawn_journal(journal: Arc<Journal>, every: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            ticker.tick().await;
+
+            let job = journal.clone();
+            match tokio::task::spawn_blocking(move || job.save()).await {
+                Ok(Ok(())) => debug!("Saved run state"),
+                Ok(Err(e)) => warn!("Failed to save run state: {}", e),
+                Err(e) => warn!("Run state save task failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Writes to a temporary file and renames it over the old one, so a crash
+/// mid-write leaves the previous contents intact
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{outpoint, temp_path, wallet};
+    use kaspa_addresses::Version;
+
+    fn state(address: &Address) -> RunState {
+        RunState {
+            version: STATE_VERSION,
+            address: address.to_string(),
+            saved_at: unix_now(),
+            run_id: Some(7),
+            next_seq: 3,
+            sent: 2,
+            failed: 1,
+            runtime_secs: 60,
+            pending: vec![PendingSpend { outpoint: outpoint(1), txid: None }],
+            completed: false,
+        }
+    }
+
+    #[test]
+    fn load_accepts_only_this_version_and_address() {
+        let wallet = wallet();
+        let address = &wallet.primary().address;
+        let path = temp_path("journal-load");
+        assert!(RunState::load(&path, address).unwrap().is_none());
+
+        state(address).save(&path).unwrap();
+        let loaded = RunState::load(&path, address).unwrap().expect("saved state loads");
+        assert_eq!(loaded.next_seq, 3);
+        assert_eq!(loaded.pending[0].outpoint, outpoint(1));
+
+        let other = Address::new(address.prefix, Version::PubKey, &[2; 32]);
+        assert!(RunState::load(&path, &other).unwrap().is_none());
+
+        RunState { version: STATE_VERSION + 1, ..state(address) }.save(&path).unwrap();
+        assert!(RunState::load(&path, address).unwrap().is_none());
+
+        std::fs::write(&path, b"{").unwrap();
+        assert!(RunState::load(&path, address).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
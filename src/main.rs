@@ -1,79 +1,152 @@
 mod config;
+mod deposit;
 mod error;
+mod funding;
+mod journal;
 mod network;
+mod notifications;
+mod pacing;
+mod selection;
+mod signer;
+mod snapshot;
 mod spam;
+mod splitting;
+mod stats;
+mod telemetry;
+#[cfg(test)]
+mod testing;
 mod transaction;
 mod utxo;
+mod wallet;
 
-use crate::config::{load_config, Cli, Config};
-use crate::error::{Result, TxGenError};
-use crate::transaction::{calculate_fee, create_splitting_transaction};
+use crate::config::{load_config, Cli, Command, Config};
+use crate::error::Result;
+use crate::snapshot::UtxoSnapshot;
+use crate::transaction::SOMPI_PER_KAS;
+use crate::wallet::Wallet;
 use clap::Parser;
-use kaspa_addresses::{Address, Version};
-use kaspa_consensus_core::tx::TransactionOutpoint;
-use kaspa_grpc_client::GrpcClient;
-use kaspa_rpc_core::{api::rpc::RpcApi, model::SubmitTransactionRequest, RpcTransaction};
-use secp256k1::{Keypair, SecretKey, SECP256K1};
-use std::str::FromStr;
+use std::path::Path;
 use std::sync::Arc;
-use tokio::time::{sleep, Duration};
-use tracing::{error, info, warn};
+use tracing::{debug, error, info};
 use tracing_subscriber::EnvFilter;
 
-#[tokio::main]
-async fn main() {
-    if let Err(e) = run().await {
+fn main() {
+    // Parse CLI arguments
+    let cli = Cli::parse();
+
+    // Load configuration
+    let (config, private_key) = match load_config(&cli) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Fatal error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Build the runtime separately from the signing pool so each can be sized on its own
+    let runtime = match build_runtime(&config) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Fatal error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = runtime.block_on(run(config, private_key, cli.command)) {
         error!("Fatal error: {}", e);
         std::process::exit(1);
     }
 }
 
-async fn run() -> Result<()> {
-    // Parse CLI arguments
-    let cli = Cli::parse();
+fn build_runtime(config: &Config) -> Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all().thread_name("tx-gen-io");
 
-    // Load configuration
-    let (config, private_key_hex) = load_config(&cli)?;
+    if config.advanced.tokio_worker_threads > 0 {
+        builder.worker_threads(config.advanced.tokio_worker_threads);
+    }
 
+    Ok(builder.build()?)
+}
+
+async fn run(mut config: Config, private_key: String, command: Option<Command>) -> Result<()> {
     // Initialize logging
     init_logging(&config)?;
 
     info!("Kaspa Transaction Generator v{}", env!("CARGO_PKG_VERSION"));
     info!("Network: {:?}", config.network.network);
 
-    // Parse private key and create keypair
-    let secret_key = SecretKey::from_str(&private_key_hex)
-        .map_err(|e| TxGenError::InvalidPrivateKey(format!("Invalid private key: {}", e)))?;
-    let keypair = Keypair::from_secret_key(&SECP256K1, &secret_key);
-
-    // Create address
-    let address = Address::new(
-        config.network.network.prefix(),
-        Version::PubKey,
-        &keypair.x_only_public_key().0.serialize(),
-    );
+    // Parse the private key and derive the wallet's addresses
+    let mut wallet = Wallet::from_secret(&private_key, &config)?;
 
-    info!("Using address: {}", address);
+    info!("Using address: {}", wallet.primary().address);
 
     // Create client pool
     let clients = network::create_client_pool(&config).await?;
 
     // Verify network matches
-    let server_info = network::verify_network(&clients[0], config.network.network, &address).await?;
+    let server_info = network::verify_network(&clients[0], config.network.network, &wallet.primary().address).await?;
     info!("Connected to {} (DAA score: {})", server_info.network_id, server_info.virtual_daa_score);
 
+    // Start from a UTXO snapshot instead of querying the node
+    let mut snapshot = match &config.snapshot.import_path {
+        Some(path) => Some(UtxoSnapshot::load(Path::new(path))?),
+        None => None,
+    };
+
+    // Find the funded addresses of an HD wallet, or take them from the snapshot
+    if wallet.is_hd() {
+        match &snapshot {
+            Some(snapshot) => wallet.restore_paths(snapshot.paths())?,
+            None => wallet.discover(&clients[0], &config).await?,
+        }
+        for key in wallet.keys() {
+            debug!("Wallet address {} ({})", key.address, key.path.as_deref().unwrap_or("-"));
+        }
+        info!("Spreading UTXOs over {} addresses", wallet.len());
+    }
+    if let Some(snapshot) = &snapshot {
+        snapshot.check(&wallet, &config)?;
+    }
+    let wallet = Arc::new(wallet);
+
+    // Hold off until the wallet is funded instead of failing on an empty balance
+    if config.deposit.wait_for_sompi > 0
+        && deposit::wait_for_funds(&clients[0], &wallet, &config).await?
+        && snapshot.take().is_some()
+    {
+        info!("Funds arrived after the snapshot was taken, fetching UTXOs from the node instead");
+    }
+
+    if let Some(Command::FundWorkers { .. }) = command {
+        return funding::fund_workers(&clients, &wallet, &config).await;
+    }
+
     // Fetch initial UTXOs
-    let utxos = utxo::fetch_spendable_utxos(&clients[0], address.clone(), &config).await?;
+    let utxos = match &snapshot {
+        Some(snapshot) => snapshot.spendable(server_info.virtual_daa_score, &config),
+        None => utxo::fetch_spendable_utxos(&clients[0], &wallet.addresses(), &config).await?,
+    };
     let current_utxo_count = utxos.len();
     let total_balance: u64 = utxos.iter().map(|(_, entry)| entry.amount).sum();
 
     info!("=== UTXO Analysis ===");
     info!("Current UTXOs: {}", current_utxo_count);
-    info!("Total balance: {:.2} KAS", total_balance as f64 / 100_000_000.0);
+    info!("Total balance: {:.2} KAS", total_balance as f64 / SOMPI_PER_KAS);
+
+    // Size the pool from the balance when asked to
+    if config.utxo.auto_target_count {
+        config.utxo.target_utxo_count = splitting::auto_target_count(&utxos, &config);
+        info!("Target UTXO count sized from balance: {}", config.utxo.target_utxo_count);
+    }
+    let config = Arc::new(config);
 
     // Check if we need to split UTXOs
     if current_utxo_count < config.utxo.target_utxo_count {
-        perform_utxo_splitting(&clients[0], &keypair, &address, utxos, &config).await?;
+        splitting::perform_utxo_splitting(&clients, &wallet, utxos, &config).await?;
+        if snapshot.take().is_some() {
+            info!("Splitting changed the UTXO set, fetching it from the node instead of the snapshot");
+        }
     } else {
         info!(
             "Already have {} UTXOs (target: {}), skipping splitting phase",
@@ -83,105 +156,7 @@ async fn run() -> Result<()> {
 
     // Run spam loop
     info!("=== Starting Transaction Spam ===");
-    spam::run_spam_loop(&clients, address, Arc::new(keypair), &config).await?;
-
-    Ok(())
-}
-
-async fn perform_utxo_splitting(
-    client: &GrpcClient,
-    keypair: &Keypair,
-    address: &Address,
-    mut utxos: Vec<(TransactionOutpoint, kaspa_consensus_core::tx::UtxoEntry)>,
-    config: &Config,
-) -> Result<()> {
-    info!("=== Phase 1: UTXO Splitting ===");
-
-    let utxos_needed = config.utxo.target_utxo_count - utxos.len();
-    info!("Need to create {} more UTXOs", utxos_needed);
-
-    // Find largest UTXO
-    let largest_utxo = utxos
-        .iter()
-        .max_by_key(|(_, e)| e.amount)
-        .ok_or_else(|| TxGenError::InsufficientFunds {
-            required: 10.0,
-            available: 0.0,
-        })?
-        .clone();
-
-    let kas_amount = largest_utxo.1.amount as f64 / 100_000_000.0;
-    info!("Using largest UTXO with {:.2} KAS for splitting", kas_amount);
-
-    if kas_amount < 10.0 {
-        return Err(TxGenError::InsufficientFunds {
-            required: 10.0,
-            available: kas_amount,
-        });
-    }
-
-    let mut current_utxo = largest_utxo;
-    let mut created = 0usize;
-
-    let transactions_count = (config.utxo.target_utxo_count + config.utxo.outputs_per_transaction - 1)
-        / config.utxo.outputs_per_transaction;
-
-    for i in 0..transactions_count {
-        let remaining_tx = transactions_count - i;
-        let outputs_this_tx = if remaining_tx == 1 {
-            config.utxo.target_utxo_count - (i * config.utxo.outputs_per_transaction)
-        } else {
-            config.utxo.outputs_per_transaction
-        };
-
-        let total_output_value = config.utxo.amount_per_utxo * outputs_this_tx as u64;
-        let estimated_fee = calculate_fee(config, 1, outputs_this_tx as u64 + 1, true);
-        let change_value = current_utxo.1.amount.saturating_sub(total_output_value + estimated_fee);
-
-        if change_value < config.utxo.min_change_sompi && i < transactions_count - 1 {
-            warn!("Insufficient funds for change in tx {}, stopping", i + 1);
-            break;
-        }
-
-        let tx = create_splitting_transaction(
-            keypair,
-            &current_utxo,
-            config.utxo.amount_per_utxo,
-            outputs_this_tx,
-            change_value,
-            address,
-            config.utxo.min_change_sompi,
-        )?;
-
-        info!("Submitting splitting transaction {} with {} outputs", i + 1, outputs_this_tx);
-
-        client
-            .submit_transaction_call(None, SubmitTransactionRequest {
-                transaction: RpcTransaction::from(&tx),
-                allow_orphan: true,
-            })
-            .await?;
-
-        created += 1;
-
-        // Update current UTXO to the change output for next iteration
-        if i < transactions_count - 1 && change_value >= config.utxo.min_change_sompi {
-            let change_outpoint = TransactionOutpoint::new(tx.id(), outputs_this_tx as u32);
-            let change_entry = kaspa_consensus_core::tx::UtxoEntry {
-                amount: change_value,
-                script_public_key: kaspa_txscript::pay_to_address_script(address),
-                block_daa_score: current_utxo.1.block_daa_score,
-                is_coinbase: false,
-            };
-            current_utxo = (change_outpoint, change_entry);
-        }
-
-        // Small delay between submissions
-        sleep(Duration::from_millis(200)).await;
-    }
-
-    info!("Created {} splitting transactions, waiting for confirmations...", created);
-    sleep(Duration::from_secs(10)).await;
+    spam::run_spam_loop(&clients, wallet, config.clone(), snapshot).await?;
 
     Ok(())
 }
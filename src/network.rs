@@ -9,20 +9,28 @@ use kaspa_rpc_core::{
 use std::sync::Arc;
 use tracing::{info, warn};
 
+pub fn rpc_url(config: &Config) -> String {
+    config.network.rpc_endpoint.clone()
+        .unwrap_or_else(|| config.network.network.grpc_url())
+}
+
+pub async fn connect_client(rpc_url: &str) -> Result<Arc<GrpcClient>> {
+    let client = GrpcClient::connect(rpc_url.to_string())
+        .await
+        .map_err(|e| TxGenError::Config(
+            format!("Failed to connect to {}: {}", rpc_url, e)
+        ))?;
+    Ok(Arc::new(client))
+}
+
 pub async fn create_client_pool(config: &Config) -> Result<Vec<Arc<GrpcClient>>> {
-    let rpc_url = config.network.rpc_endpoint.clone()
-        .unwrap_or_else(|| config.network.network.grpc_url());
+    let rpc_url = rpc_url(config);
 
     info!("Connecting to {} at {}", config.network.network.expected_hint(), rpc_url);
 
     let mut clients = Vec::with_capacity(config.advanced.client_pool_size);
     for i in 0..config.advanced.client_pool_size {
-        let client = GrpcClient::connect(rpc_url.clone())
-            .await
-            .map_err(|e| TxGenError::Config(
-                format!("Failed to connect to {}: {}", rpc_url, e)
-            ))?;
-        clients.push(Arc::new(client));
+        clients.push(connect_client(&rpc_url).await?);
 
         if i == 0 {
             info!("Successfully connected to Kaspa node");
@@ -0,0 +1,90 @@
+use crate::config::Config;
+use crate::error::{Result, TxGenError};
+use crate::network::rpc_url;
+use kaspa_addresses::Address;
+use kaspa_grpc_client::GrpcClient;
+use kaspa_notify::{
+    listener::ListenerId,
+    scope::{Scope, UtxosChangedScope},
+};
+use kaspa_rpc_core::{api::rpc::RpcApi, notify::mode::NotificationMode, Notification, UtxosChangedNotification};
+use tracing::{info, warn};
+
+/// UtxosChanged subscription for our addresses on a dedicated connection
+pub struct UtxoSubscription {
+    client: GrpcClient,
+    receiver: async_channel::Receiver<Notification>,
+    addresses: Vec<Address>,
+    connected: bool,
+}
+
+impl UtxoSubscription {
+    pub async fn connect(config: &Config, addresses: Vec<Address>) -> Result<Self> {
+        let url = rpc_url(config);
+        let client = GrpcClient::connect_with_args(
+            NotificationMode::Direct,
+            url.clone(),
+            None,
+            true,
+            None,
+            false,
+            Some(500_000),
+            Default::default(),
+        )
+        .await
+        .map_err(|e| TxGenError::Config(format!("Failed to connect to {}: {}", url, e)))?;
+        client.start(None).await;
+
+        let receiver = client.notification_channel_receiver();
+        let subscription = Self { client, receiver, addresses, connected: true };
+        subscription.subscribe().await?;
+
+        info!("Subscribed to UTXO changes for {} address(es)", subscription.addresses.len());
+        Ok(subscription)
+    }
+
+    /// Next UtxosChanged notification, skipping any other kind.
+    /// Returns `None` once the notification channel is closed.
+    pub async fn recv(&self) -> Option<UtxosChangedNotification> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(Notification::UtxosChanged(changes)) => return Some(changes),
+                Ok(_) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Re-subscribes after the connection comes back. Returns true when that happened,
+    /// since changes may have been missed and the full UTXO set must be refetched.
+    pub async fn check_reconnect(&mut self) -> bool {
+        let connected = self.client.is_connected();
+        let reconnected = connected && !self.connected;
+
+        if !connected && self.connected {
+            warn!("Lost UTXO notification connection, waiting for reconnect");
+        }
+        self.connected = connected;
+
+        if reconnected {
+            info!("UTXO notification connection restored, resubscribing");
+            if let Err(e) = self.subscribe().await {
+                warn!("Failed to resubscribe to UTXO changes: {}", e);
+                self.connected = false;
+                return false;
+            }
+        }
+
+        reconnected
+    }
+
+    async fn subscribe(&self) -> Result<()> {
+        self.client
+            .start_notify(
+                ListenerId::default(),
+                Scope::UtxosChanged(UtxosChangedScope::new(self.addresses.clone())),
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+
+/// How send opportunities are spread over time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Tokens accrue continuously: evenly spaced sends
+    Uniform,
+    /// Sends arrive with exponentially distributed gaps at the mean rate
+    Poisson,
+}
+
+/// Token-bucket pacer with fractional tokens. Tokens accrue at `rate`, either
+/// continuously or as Poisson arrivals, so the long-run rate is exact regardless
+/// of wakeup granularity, and at most `burst` tokens can accumulate while the
+/// sender is stalled.
+pub struct Pacer {
+    schedule: Schedule,
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+    next_arrival: Instant,
+}
+
+impl Pacer {
+    pub fn new(schedule: Schedule, rate: f64, burst: u64) -> Self {
+        let now = Instant::now();
+        let mut pacer = Self {
+            schedule,
+            rate,
+            burst: (burst.max(1)) as f64,
+            tokens: 0.0,
+            last: now,
+            next_arrival: now,
+        };
+        if schedule == Schedule::Poisson && rate > 0.0 {
+            pacer.next_arrival = now + pacer.next_gap();
+        }
+        pacer
+    }
+
+    /// Default burst: 10ms worth of tokens, at least one
+    pub fn auto_burst(rate: f64) -> u64 {
+        ((rate / 100.0).ceil() as u64).max(1)
+    }
+
+    /// Waits until at least one token is available and takes up to `max` of them.
+    /// Returns the number of tokens taken and how late the wakeup was.
+    /// Cancel-safe: tokens are only consumed when the future completes.
+    pub async fn acquire(&mut self, max: u64) -> (u64, Duration) {
+        if self.rate <= 0.0 || max == 0 {
+            return std::future::pending().await;
+        }
+
+        let mut lag = Duration::ZERO;
+        match self.schedule {
+            Schedule::Uniform => {
+                self.refill(Instant::now());
+
+                if self.tokens < 1.0 {
+                    let deadline = self.last + Duration::from_secs_f64((1.0 - self.tokens) / self.rate);
+                    sleep_until(deadline).await;
+
+                    let now = Instant::now();
+                    lag = now.saturating_duration_since(deadline);
+                    self.refill(now);
+                }
+            }
+            Schedule::Poisson => {
+                if self.tokens < 1.0 {
+                    sleep_until(self.next_arrival).await;
+                    lag = Instant::now().saturating_duration_since(self.next_arrival);
+                }
+
+                let now = Instant::now();
+                while self.next_arrival <= now {
+                    self.tokens = (self.tokens + 1.0).min(self.burst);
+                    self.next_arrival += self.next_gap();
+                }
+            }
+        }
+
+        let taken = (self.tokens.floor() as u64).min(max);
+        self.tokens -= taken as f64;
+        (taken, lag)
+    }
+
+    /// Returns tokens that could not be used, still bounded by the burst size
+    pub fn refund(&mut self, tokens: u64) {
+        self.tokens = (self.tokens + tokens as f64).min(self.burst);
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+
+    /// Exponentially distributed inter-arrival time with mean `1 / rate`
+    fn next_gap(&self) -> Duration {
+        let u: f64 = secp256k1::rand::random();
+        Duration::from_secs_f64(-(1.0 - u).ln() / self.rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Slow enough that the time a test takes adds no whole token
+    const SLOW: f64 = 0.01;
+
+    #[test]
+    fn auto_burst_covers_ten_milliseconds() {
+        assert_eq!(Pacer::auto_burst(1000.0), 10);
+        assert_eq!(Pacer::auto_burst(150.0), 2);
+        assert_eq!(Pacer::auto_burst(1.0), 1);
+    }
+
+    #[test]
+    fn refill_accrues_at_the_rate_up_to_the_burst() {
+        let mut pacer = Pacer::new(Schedule::Uniform, 100.0, 10);
+        let start = pacer.last;
+
+        pacer.refill(start + Duration::from_millis(50));
+        assert!((pacer.tokens - 5.0).abs() < 1e-9);
+
+        pacer.refill(start + Duration::from_secs(1));
+        assert_eq!(pacer.tokens, 10.0);
+    }
+
+    #[test]
+    fn refund_is_bounded_by_the_burst() {
+        let mut pacer = Pacer::new(Schedule::Uniform, SLOW, 4);
+
+        pacer.refund(3);
+        assert_eq!(pacer.tokens, 3.0);
+        pacer.refund(3);
+        assert_eq!(pacer.tokens, 4.0);
+    }
+
+    #[tokio::test]
+    async fn acquire_takes_at_most_max() {
+        let mut pacer = Pacer::new(Schedule::Uniform, SLOW, 10);
+        pacer.tokens = 5.0;
+
+        assert_eq!(pacer.acquire(2).await.0, 2);
+        assert_eq!(pacer.acquire(10).await.0, 3);
+    }
+
+    #[tokio::test]
+    async fn refunded_tokens_can_be_taken_again() {
+        let mut pacer = Pacer::new(Schedule::Uniform, SLOW, 10);
+        pacer.tokens = 4.0;
+
+        let (taken, _) = pacer.acquire(4).await;
+        assert_eq!(taken, 4);
+        pacer.refund(3);
+        assert_eq!(pacer.acquire(10).await.0, 3);
+    }
+
+    #[tokio::test]
+    async fn poisson_tokens_are_refunded_too() {
+        let mut pacer = Pacer::new(Schedule::Poisson, SLOW, 10);
+        pacer.next_arrival = Instant::now() + Duration::from_secs(3600);
+        pacer.tokens = 2.0;
+
+        assert_eq!(pacer.acquire(2).await.0, 2);
+        pacer.refund(1);
+        assert_eq!(pacer.acquire(5).await.0, 1);
+    }
+}
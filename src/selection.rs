@@ -0,0 +1,219 @@
+use crate::config::{Config, SelectionStrategy};
+use crate::transaction::SOMPI_PER_KAS;
+use kaspa_consensus_core::tx::UtxoEntry as CoreUtxoEntry;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Selection strategy plus the amount `closest` aims for
+#[derive(Debug, Clone, Copy)]
+pub struct Selection {
+    pub strategy: SelectionStrategy,
+    pub target: u64,
+}
+
+impl Selection {
+    pub fn from_config(config: &Config) -> Self {
+        let target = match config.utxo.selection_target_sompi {
+            0 => config.utxo.amount_per_utxo,
+            target => target,
+        };
+        Self { strategy: config.utxo.selection, target }
+    }
+}
+
+/// Hand-out order of available UTXOs under a selection strategy. Entries are never
+/// removed eagerly: the pool skips ids that are no longer available when popped, and
+/// `retain` drops them once they pile up.
+pub struct ReadyQueue {
+    selection: Selection,
+    // (amount or DAA score, slot id) for the ordered strategies
+    ordered: BTreeSet<(u64, u32)>,
+    // Slot ids for the random strategy
+    shuffled: Vec<u32>,
+}
+
+impl ReadyQueue {
+    pub fn new(selection: Selection) -> Self {
+        Self { selection, ordered: BTreeSet::new(), shuffled: Vec::new() }
+    }
+
+    pub fn push(&mut self, id: u32, entry: &CoreUtxoEntry) {
+        match self.selection.strategy {
+            SelectionStrategy::Random => self.shuffled.push(id),
+            SelectionStrategy::OldestFirst => {
+                self.ordered.insert((entry.block_daa_score, id));
+            }
+            _ => {
+                self.ordered.insert((entry.amount, id));
+            }
+        }
+    }
+
+    /// Next UTXO to hand out
+    pub fn pop_front(&mut self) -> Option<u32> {
+        match self.selection.strategy {
+            SelectionStrategy::LargestFirst => self.ordered.pop_last().map(|(_, id)| id),
+            SelectionStrategy::SmallestFirst | SelectionStrategy::OldestFirst => {
+                self.ordered.pop_first().map(|(_, id)| id)
+            }
+            SelectionStrategy::Random => self.pop_random(),
+            SelectionStrategy::Closest => {
+                let target = self.selection.target;
+                let above = self.ordered.range((target, 0)..).next().copied();
+                let below = self.ordered.range(..(target, 0)).next_back().copied();
+                let nearest = match (above, below) {
+                    (Some(a), Some(b)) => Some(if a.0 - target <= target - b.0 { a } else { b }),
+                    (a, b) => a.or(b),
+                };
+                nearest.map(|key| {
+                    self.ordered.remove(&key);
+                    key.1
+                })
+            }
+        }
+    }
+
+    /// UTXO the strategy wants least, for handing over to other shards
+    pub fn pop_back(&mut self) -> Option<u32> {
+        match self.selection.strategy {
+            SelectionStrategy::LargestFirst => self.ordered.pop_first().map(|(_, id)| id),
+            SelectionStrategy::SmallestFirst | SelectionStrategy::OldestFirst => {
+                self.ordered.pop_last().map(|(_, id)| id)
+            }
+            SelectionStrategy::Random => self.pop_random(),
+            SelectionStrategy::Closest => {
+                let target = self.selection.target;
+                let first = self.ordered.first().copied();
+                let last = self.ordered.last().copied();
+                let farthest = match (first, last) {
+                    (Some(f), Some(l)) => Some(if l.0.abs_diff(target) >= f.0.abs_diff(target) { l } else { f }),
+                    _ => None,
+                };
+                farthest.map(|key| {
+                    self.ordered.remove(&key);
+                    key.1
+                })
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ordered.len() + self.shuffled.len()
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(u32) -> bool) {
+        self.ordered.retain(|&(_, id)| keep(id));
+        self.shuffled.retain(|&id| keep(id));
+    }
+
+    fn pop_random(&mut self) -> Option<u32> {
+        if self.shuffled.is_empty() {
+            return None;
+        }
+        let index = secp256k1::rand::random::<usize>() % self.shuffled.len();
+        Some(self.shuffled.swap_remove(index))
+    }
+}
+
+/// Shape of the available UTXOs, for judging how a strategy treats the pool
+#[derive(Debug, Default, Clone)]
+pub struct PoolStats {
+    pub count: usize,
+    pub total: u64,
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+    /// UTXOs too small to pay for another spam transaction
+    pub dust: usize,
+    pub oldest_daa: u64,
+    pub newest_daa: u64,
+}
+
+impl PoolStats {
+    /// Summarises `(amount, block DAA score)` pairs; `dust_below` marks unusable amounts
+    pub fn from_entries(mut entries: Vec<(u64, u64)>, dust_below: u64) -> Self {
+        if entries.is_empty() {
+            return Self::default();
+        }
+
+        entries.sort_unstable_by_key(|&(amount, _)| amount);
+        Self {
+            count: entries.len(),
+            total: entries.iter().map(|&(amount, _)| amount).sum(),
+            min: entries[0].0,
+            median: entries[entries.len() / 2].0,
+            max: entries[entries.len() - 1].0,
+            dust: entries.iter().take_while(|&&(amount, _)| amount < dust_below).count(),
+            oldest_daa: entries.iter().map(|&(_, daa)| daa).min().unwrap_or_default(),
+            newest_daa: entries.iter().map(|&(_, daa)| daa).max().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} UTXOs, {:.2} KAS | min: {:.4} median: {:.4} max: {:.4} KAS | dust: {} | DAA {}..{}",
+            self.count,
+            self.total as f64 / SOMPI_PER_KAS,
+            self.min as f64 / SOMPI_PER_KAS,
+            self.median as f64 / SOMPI_PER_KAS,
+            self.max as f64 / SOMPI_PER_KAS,
+            self.dust,
+            self.oldest_daa,
+            self.newest_daa
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kaspa_consensus_core::tx::ScriptPublicKey;
+    use std::iter;
+    use SelectionStrategy::*;
+
+    /// Slots 0 to 3 holding 300, 100, 200 and 500 sompi, at DAA scores 5, 9, 1 and 3
+    fn queue(strategy: SelectionStrategy, target: u64) -> ReadyQueue {
+        let mut queue = ReadyQueue::new(Selection { strategy, target });
+        for (id, (amount, daa)) in [(300, 5), (100, 9), (200, 1), (500, 3)].into_iter().enumerate() {
+            queue.push(id as u32, &CoreUtxoEntry::new(amount, ScriptPublicKey::from_vec(0, vec![0x51]), daa, false));
+        }
+        queue
+    }
+
+    fn drain(mut queue: ReadyQueue) -> Vec<u32> {
+        iter::from_fn(|| queue.pop_front()).collect()
+    }
+
+    #[test]
+    fn ordered_strategies_hand_out_in_their_order() {
+        assert_eq!(drain(queue(LargestFirst, 0)), vec![3, 0, 2, 1]);
+        assert_eq!(drain(queue(SmallestFirst, 0)), vec![1, 2, 0, 3]);
+        assert_eq!(drain(queue(OldestFirst, 0)), vec![2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn closest_hands_out_the_nearest_amount_first() {
+        // 200 and 300 tie for 250; the one above wins
+        assert_eq!(drain(queue(Closest, 250)), vec![0, 2, 1, 3]);
+        assert_eq!(drain(queue(Closest, 1000)), vec![3, 0, 2, 1]);
+    }
+
+    #[test]
+    fn random_hands_out_every_slot_once() {
+        let mut ids = drain(queue(Random, 0));
+        ids.sort_unstable();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn pop_back_takes_what_the_strategy_wants_least() {
+        assert_eq!(queue(LargestFirst, 0).pop_back(), Some(1));
+        assert_eq!(queue(SmallestFirst, 0).pop_back(), Some(3));
+        assert_eq!(queue(OldestFirst, 0).pop_back(), Some(1));
+        assert_eq!(queue(Closest, 250).pop_back(), Some(3));
+        assert_eq!(queue(Closest, 450).pop_back(), Some(1));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::error::{Result, TxGenError};
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use tokio::sync::oneshot;
+use tracing::info;
+
+/// Dedicated rayon pool for CPU-bound signing, kept apart from the tokio workers
+/// so signing never blocks the gRPC I/O threads.
+pub struct SigningPool {
+    pool: rayon::ThreadPool,
+}
+
+impl SigningPool {
+    pub fn new(threads: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("tx-signer-{}", i))
+            .build()
+            .map_err(|e| TxGenError::Config(format!("Failed to build signing pool: {}", e)))?;
+
+        info!("Created signing pool with {} threads", pool.current_num_threads());
+        Ok(Self { pool })
+    }
+
+    /// Runs `job` on the signing pool and awaits its result without blocking the runtime.
+    /// Parallel iterators inside `job` execute on the same pool. A panicking job is
+    /// reported as a signing error instead of letting rayon abort the process.
+    pub async fn run<F, R>(&self, job: F) -> Result<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.pool.spawn(move || {
+            let _ = tx.send(panic::catch_unwind(AssertUnwindSafe(job)));
+        });
+
+        match rx.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(payload)) => {
+                Err(TxGenError::Signing(format!("Signing job panicked: {}", panic_message(&*payload))))
+            }
+            Err(_) => Err(TxGenError::Signing("Signing job dropped before completion".to_string())),
+        }
+    }
+}
+
+/// Text of a panic payload, which `panic!` makes a `&str` or a `String`
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
@@ -0,0 +1,220 @@
+use crate::config::Config;
+use crate::error::{Result, TxGenError};
+use crate::journal::{unix_now, write_atomic};
+use crate::utxo::{lock_maturity, lock_shard, spendable_at, SharedMaturity, UtxoSet, UtxoShard, UtxoState};
+use crate::wallet::Wallet;
+use kaspa_consensus_core::tx::{TransactionId, TransactionOutpoint, UtxoEntry as CoreUtxoEntry};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::info;
+
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Where a UTXO stood in the pool when the snapshot was taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotState {
+    Available,
+    Reserved,
+    Submitted,
+    /// Reported by the node but not deep enough to spend
+    Maturing,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotUtxo {
+    pub outpoint: TransactionOutpoint,
+    pub entry: CoreUtxoEntry,
+    pub state: SnapshotState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spender: Option<TransactionId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotAddress {
+    pub address: String,
+    /// `chain/index` below the account key, for HD wallets
+    pub path: Option<String>,
+}
+
+/// The generator's view of its UTXO set, for starting a run without querying the node
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UtxoSnapshot {
+    pub version: u32,
+    pub network: String,
+    /// Unix time of the capture, in seconds
+    pub saved_at: u64,
+    pub addresses: Vec<SnapshotAddress>,
+    pub utxos: Vec<SnapshotUtxo>,
+}
+
+impl UtxoSnapshot {
+    pub fn capture(wallet: &Wallet, config: &Config, shards: &[UtxoShard], maturing: &SharedMaturity) -> Self {
+        let mut utxos = Vec::new();
+        for shard in shards {
+            let shard = lock_shard(shard);
+            utxos.extend(shard.held().map(|(outpoint, entry, state, spender)| SnapshotUtxo {
+                outpoint: *outpoint,
+                entry: entry.clone(),
+                state: match state {
+                    UtxoState::Available => SnapshotState::Available,
+                    UtxoState::Reserved => SnapshotState::Reserved,
+                    UtxoState::Submitted => SnapshotState::Submitted,
+                },
+                spender,
+            }));
+        }
+        utxos.extend(lock_maturity(maturing).entries().map(|(outpoint, entry, _)| SnapshotUtxo {
+            outpoint: *outpoint,
+            entry: entry.clone(),
+            state: SnapshotState::Maturing,
+            spender: None,
+        }));
+
+        Self {
+            version: SNAPSHOT_VERSION,
+            network: format!("{:?}", config.network.network),
+            saved_at: unix_now(),
+            addresses: wallet
+                .keys()
+                .iter()
+                .map(|key| SnapshotAddress { address: key.address.to_string(), path: key.path.clone() })
+                .collect(),
+            utxos,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_vec(self)
+            .map_err(|e| TxGenError::Parse(format!("Failed to encode snapshot: {}", e)))?;
+        write_atomic(path, &data)?;
+        info!("Saved snapshot of {} UTXOs to {}", self.utxos.len(), path.display());
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        let snapshot: UtxoSnapshot = serde_json::from_slice(&data)
+            .map_err(|e| TxGenError::Parse(format!("Invalid snapshot {}: {}", path.display(), e)))?;
+
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(TxGenError::Parse(format!(
+                "Snapshot {} has unsupported version {}",
+                path.display(),
+                snapshot.version
+            )));
+        }
+
+        info!(
+            "Loaded snapshot of {} UTXOs over {} addresses, taken {}s ago",
+            snapshot.utxos.len(),
+            snapshot.addresses.len(),
+            unix_now().saturating_sub(snapshot.saved_at)
+        );
+        Ok(snapshot)
+    }
+
+    /// Address paths to derive instead of scanning the wallet
+    pub fn paths(&self) -> impl Iterator<Item = &str> + '_ {
+        self.addresses.iter().filter_map(|a| a.path.as_deref())
+    }
+
+    /// Fails unless the snapshot was taken on this network with this wallet
+    pub fn check(&self, wallet: &Wallet, config: &Config) -> Result<()> {
+        let network = format!("{:?}", config.network.network);
+        if self.network != network {
+            return Err(TxGenError::Config(format!(
+                "Snapshot was taken on {}, not {}",
+                self.network, network
+            )));
+        }
+
+        let primary = wallet.primary().address.to_string();
+        if self.addresses.first().map(|a| a.address.as_str()) != Some(primary.as_str()) {
+            return Err(TxGenError::Config(format!("Snapshot does not belong to {}", primary)));
+        }
+        Ok(())
+    }
+
+    /// UTXOs spendable at `virtual_daa_score`, ignoring pending spends
+    pub fn spendable(&self, virtual_daa_score: u64, config: &Config) -> Vec<(TransactionOutpoint, CoreUtxoEntry)> {
+        self.utxos
+            .iter()
+            .filter(|u| u.state != SnapshotState::Submitted)
+            .filter(|u| spendable_at(&u.entry, config) <= virtual_daa_score)
+            .map(|u| (u.outpoint, u.entry.clone()))
+            .collect()
+    }
+
+    /// Splits the snapshot into a UTXO set, by maturity at `virtual_daa_score`, and the
+    /// spends that were submitted, which stay locked until reconciled. Reservations
+    /// belonged to the previous process and are available again.
+    pub fn into_set(
+        self,
+        virtual_daa_score: u64,
+        config: &Config,
+    ) -> (UtxoSet, Vec<(TransactionOutpoint, CoreUtxoEntry, Option<TransactionId>)>) {
+        let mut set = UtxoSet::default();
+        let mut submitted = Vec::new();
+
+        for utxo in self.utxos {
+            if utxo.state == SnapshotState::Submitted {
+                submitted.push((utxo.outpoint, utxo.entry, utxo.spender));
+                continue;
+            }
+
+            let ready_at = spendable_at(&utxo.entry, config);
+            if ready_at <= virtual_daa_score {
+                set.spendable.push((utxo.outpoint, utxo.entry));
+            } else {
+                set.immature.push((utxo.outpoint, utxo.entry, ready_at));
+            }
+        }
+
+        (set, submitted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selection::Selection;
+    use crate::testing::{outpoint, temp_path, txid, utxo, wallet};
+    use crate::utxo::UtxoShards;
+    use kaspa_consensus_core::tx::ScriptPublicKey;
+
+    #[test]
+    fn snapshots_round_trip_pool_state() {
+        let (wallet, config) = (wallet(), Config::default());
+        let coinbase = CoreUtxoEntry::new(500, ScriptPublicKey::from_vec(0, vec![0x51]), 50, true);
+        let set = UtxoSet {
+            spendable: vec![utxo(1, 100), utxo(2, 200), utxo(3, 300)],
+            immature: vec![(outpoint(4), coinbase, 1050)],
+            ..Default::default()
+        };
+        let shards = UtxoShards::new(set, 1, Selection::from_config(&config));
+        {
+            let mut shard = lock_shard(&shards.shards()[0]);
+            let mut batch = Vec::new();
+            shard.take_batch(2, &mut batch);
+            shard.mark_submitted(&batch[0].0, txid(0xaa));
+        }
+
+        let path = temp_path("snapshot");
+        UtxoSnapshot::capture(&wallet, &config, shards.shards(), &shards.maturity_queue()).save(&path).unwrap();
+        let snapshot = UtxoSnapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        snapshot.check(&wallet, &config).unwrap();
+        let other = Wallet::from_secret(&"02".repeat(32), &config).unwrap();
+        assert!(snapshot.check(&other, &config).is_err());
+
+        // The reservation is free again, the submitted spend stays locked and the coinbase keeps maturing
+        let (set, submitted) = snapshot.into_set(100, &config);
+        assert_eq!(set.spendable.len(), 2);
+        assert_eq!(set.immature.len(), 1);
+        assert_eq!((set.immature[0].0, set.immature[0].2), (outpoint(4), 1050));
+        assert_eq!(submitted.len(), 1);
+        assert_eq!(submitted[0].2, Some(txid(0xaa)));
+    }
+}
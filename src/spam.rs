@@ -1,37 +1,166 @@
-use crate::config::Config;
+use crate::config::{Config, ReusePolicy, SpamMode};
 use crate::error::Result;
-use crate::transaction::{calculate_fee, create_spam_transaction};
-use crate::utxo::UtxoManager;
+use crate::journal::{reconcile, spawn_journal, Journal, RunState};
+use crate::notifications::UtxoSubscription;
+use crate::pacing::{Pacer, Schedule};
+use crate::selection::Selection;
+use crate::signer::SigningPool;
+use crate::snapshot::UtxoSnapshot;
+use crate::splitting::spawn_replenisher;
+use crate::stats::{SpamStats, StatsSnapshot};
+use crate::telemetry::spawn_telemetry;
+use crate::transaction::{
+    calculate_fee, create_spam_transaction, encode_run_payload, spam_output, RUN_PAYLOAD_LEN,
+};
+use crate::utxo::{
+    fetch_utxo_set, lock_maturity, lock_shard, pool_stats, spawn_refresher, SharedMaturity, UtxoShard, UtxoShards,
+};
+use crate::wallet::Wallet;
 use futures::stream::{FuturesUnordered, StreamExt};
-use kaspa_addresses::Address;
 use kaspa_consensus_core::tx::{Transaction, TransactionOutpoint, UtxoEntry as CoreUtxoEntry};
 use kaspa_grpc_client::GrpcClient;
-use kaspa_rpc_core::{api::rpc::RpcApi, model::SubmitTransactionRequest, RpcTransaction};
+use kaspa_rpc_core::{
+    api::rpc::RpcApi,
+    model::{GetServerInfoRequest, SubmitTransactionRequest},
+    RpcTransaction,
+};
+use rayon::iter::Either;
 use rayon::prelude::*;
-use secp256k1::Keypair;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::time::{interval, MissedTickBehavior};
 use tracing::{debug, error, info, warn};
 
+/// Shards below this many available UTXOs receive UTXOs from the fullest shard
+const REBALANCE_LOW_WATER: usize = 8;
+
+/// Stats ticks between pool statistics reports
+const POOL_STATS_EVERY: u64 = 30;
+
+/// A signed transaction waiting in the buffer, with the outpoint it spends
+struct SignedTx {
+    tx: Transaction,
+    outpoint: TransactionOutpoint,
+}
+
+/// A reserved UTXO the producer built no transaction from
+enum Unbuilt {
+    /// Building failed and may work next time: hand it back out
+    Retry(TransactionOutpoint),
+    /// Too small to pay the fee, or locked to a key we do not hold: drop it from the pool
+    Unusable(TransactionOutpoint),
+}
+
+/// Everything a worker needs: its own UTXO shard and client slice, plus shared signing state
+struct Worker {
+    id: usize,
+    shard: UtxoShard,
+    clients: Vec<Arc<GrpcClient>>,
+    wallet: Arc<Wallet>,
+    config: Arc<Config>,
+    signing_pool: Arc<SigningPool>,
+    run_id: Option<u64>,
+    next_seq: Arc<AtomicU64>,
+    stats: Arc<SpamStats>,
+    tps: f64,
+    concurrency: usize,
+}
+
 pub async fn run_spam_loop(
     clients: &[Arc<GrpcClient>],
-    address: Address,
-    keypair: Arc<Keypair>,
-    config: &Config,
+    wallet: Arc<Wallet>,
+    config: Arc<Config>,
+    snapshot: Option<UtxoSnapshot>,
 ) -> Result<()> {
     let client0 = clients[0].clone();
-    let tps_tx = spawn_tps_logger();
+    let addresses = wallet.addresses();
+    let address = &wallet.primary().address;
+
+    // Pick up where a previous run stopped, if it left state behind. A run that shut
+    // down cleanly is not resumed, but its spends may still be in the mempool.
+    let state_path = PathBuf::from(&config.state.path);
+    let previous = if config.state.enabled && config.state.resume {
+        RunState::load(&state_path, address)?
+    } else {
+        None
+    };
+    let resumed = previous.as_ref().filter(|state| !state.completed);
+
+    // Resolve the run tag embedded in transaction payloads
+    let run_id = config.spam.tag_payloads.then(|| {
+        config.spam.run_id.as_deref()
+            .and_then(|id| u64::from_str_radix(id, 16).ok())
+            .or_else(|| resumed.and_then(|state| state.run_id))
+            .unwrap_or_else(secp256k1::rand::random)
+    });
+    if let Some(run_id) = run_id {
+        info!("Tagging transaction payloads with run ID {:016x}", run_id);
+    }
+
+    // Subscribe before the initial fetch so no change falls in between
+    let subscription = if config.utxo.use_notifications {
+        Some(UtxoSubscription::connect(&config, addresses.clone()).await?)
+    } else {
+        None
+    };
 
-    // Initialize UTXO manager
-    let initial_utxos = crate::utxo::fetch_spendable_utxos(&client0, address.clone(), config).await?;
-    let mut utxo_manager = UtxoManager::new(initial_utxos);
+    // Initialize one UTXO shard per worker
+    let worker_count = config.spam.workers.max(1);
+    let virtual_daa_score = client0.get_server_info_call(None, GetServerInfoRequest {}).await?.virtual_daa_score;
+    let (mut initial_set, mut still_pending) = match snapshot {
+        Some(snapshot) => snapshot.into_set(virtual_daa_score, &config),
+        None => (fetch_utxo_set(&client0, &addresses, &config).await?, Vec::new()),
+    };
+
+    // Keep outpoints the previous run spent locked while their transactions are in the mempool
+    if let Some(state) = &previous {
+        let (locked, summary) = reconcile(&client0, &addresses, state, &mut initial_set).await?;
+        if state.completed {
+            info!(
+                "Previous run completed {}s ago, starting a new one | pending spends: {} in mempool, {} confirmed, {} lost, {} untracked",
+                state.age_secs(),
+                summary.in_mempool,
+                summary.confirmed,
+                summary.lost,
+                summary.untracked
+            );
+        } else {
+            info!(
+                "Resuming run saved {}s ago: {} sent, {} failed | pending spends: {} in mempool, {} confirmed, {} lost, {} untracked",
+                state.age_secs(),
+                state.sent,
+                state.failed,
+                summary.in_mempool,
+                summary.confirmed,
+                summary.lost,
+                summary.untracked
+            );
+        }
+        still_pending.extend(locked);
+    }
+
+    let initial_count = initial_set.spendable.len();
+    let mut utxo_shards = UtxoShards::new(initial_set, worker_count, Selection::from_config(&config));
+    utxo_shards.restore_submitted(still_pending);
+    let shards = utxo_shards.shards().to_vec();
+    let maturing = utxo_shards.maturity_queue();
+
+    // UTXOs below this cannot pay for another spam transaction
+    let payload_len = if run_id.is_some() { RUN_PAYLOAD_LEN } else { 0 };
+    let dust_below = config.utxo.min_change_sompi + calculate_fee(&config, 1, 1, payload_len, false);
+    info!("Pool [{:?}]: {}", config.utxo.selection, pool_stats(&shards, dust_below));
+    log_maturing(&maturing, virtual_daa_score, &config);
 
     info!(
-        "Starting spam loop: {} TPS target, {} UTXOs available",
+        "Starting spam loop: {:?} mode, {} TPS target, {} UTXOs available, {} workers",
+        config.spam.mode,
         config.spam.target_tps,
-        utxo_manager.available_count()
+        initial_count,
+        worker_count
     );
 
     // Apply safety cap if not unleashed
@@ -48,221 +177,620 @@ pub async fn run_spam_loop(
         );
     }
 
-    // Setup tickers
-    let mut ticker = interval(Duration::from_millis(config.spam.millis_per_tick));
-    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // Start the workers, each with an equal share of the rate
+    let signing_pool = Arc::new(SigningPool::new(config.advanced.signing_threads)?);
+    let (base_sent, base_failed, first_seq, runtime_offset) = resumed
+        .map(|state| (state.sent, state.failed, state.next_seq, Duration::from_secs(state.runtime_secs)))
+        .unwrap_or_default();
+    let stats = Arc::new(SpamStats::new(worker_count).resume_from(base_sent, base_failed));
+    let next_seq = Arc::new(AtomicU64::new(first_seq));
+
+    let mut handles = Vec::with_capacity(worker_count * 2);
+    for (id, shard) in shards.iter().enumerate() {
+        let worker = Arc::new(Worker {
+            id,
+            shard: shard.clone(),
+            clients: client_slice(clients, id, worker_count),
+            wallet: wallet.clone(),
+            config: config.clone(),
+            signing_pool: signing_pool.clone(),
+            run_id,
+            next_seq: next_seq.clone(),
+            stats: stats.clone(),
+            tps: effective_tps as f64 / worker_count as f64,
+            concurrency: share(config.spam.concurrency, worker_count, id),
+        });
+
+        let (buffer_tx, buffer_rx) = channel::<SignedTx>(config.spam.presign_buffer_size.max(1));
+        handles.push(tokio::spawn(run_producer(worker.clone(), buffer_tx)));
+        handles.push(tokio::spawn(run_submitter(worker, buffer_rx)));
+    }
+
+    // Split large UTXOs back into the pool when it runs low
+    if config.utxo.replenish {
+        handles.push(spawn_replenisher(
+            shards.clone(),
+            clients.to_vec(),
+            signing_pool.clone(),
+            wallet.clone(),
+            config.clone(),
+        ));
+    }
+
+    // Journal pending spends and counters for a crash-safe restart
+    let journal = config.state.enabled.then(|| {
+        Arc::new(Journal::new(
+            state_path,
+            address,
+            run_id,
+            shards.clone(),
+            stats.clone(),
+            next_seq.clone(),
+            runtime_offset,
+        ))
+    });
+    if let Some(journal) = &journal {
+        let every = Duration::from_millis(config.state.save_interval_ms.max(100));
+        handles.push(spawn_journal(journal.clone(), every));
+    }
+
+    // Node info is polled out of band and read from the latest snapshot
+    let (telemetry, telemetry_handle) = spawn_telemetry(&config).await?;
+    handles.push(telemetry_handle);
+
+    // Keep shards up to date and balanced in the background
+    handles.push(spawn_refresher(
+        utxo_shards,
+        client0.clone(),
+        addresses,
+        config.clone(),
+        REBALANCE_LOW_WATER,
+        telemetry.clone(),
+        subscription,
+    ));
+
+    // Coordinator: duration limit and aggregated stats. A resumed run only gets what is
+    // left of the duration, matching the runtime it reports.
+    let run_for = match config.spam.duration_seconds {
+        0 => Duration::MAX,
+        secs => Duration::from_secs(secs).saturating_sub(runtime_offset),
+    };
+    if config.spam.duration_seconds > 0 && !runtime_offset.is_zero() {
+        info!(
+            "{}s of the {}s duration already ran, {}s left",
+            runtime_offset.as_secs(),
+            config.spam.duration_seconds,
+            run_for.as_secs()
+        );
+    }
+    let deadline = tokio::time::sleep(run_for);
+    tokio::pin!(deadline);
 
     let mut stats_ticker = interval(Duration::from_secs(1));
     stats_ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
-    // Stats tracking
+    let export_path = config.snapshot.export_path.as_ref().map(PathBuf::from);
+    let export_every = config.snapshot.export_interval_secs;
+    let mut export_ticker = interval(Duration::from_secs(export_every.max(1)));
+    export_ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    export_ticker.reset();
+
     let start = Instant::now();
     let mut stats_start = Instant::now();
-    let mut sent_since_reset = 0u64;
-
-    // Pacing calculation
-    let target_per_tick = (effective_tps as f64) * (config.spam.millis_per_tick as f64) / 1000.0;
-    let mut carry = 0.0;
-
-    // Async submit queue
-    let mut inflight: FuturesUnordered<_> = FuturesUnordered::new();
-    let mut round_robin_idx = 0usize;
+    let mut last_sent = base_sent;
+    let mut last_10: VecDeque<u64> = VecDeque::with_capacity(10);
+    let mut ticks = 0u64;
 
     loop {
         tokio::select! {
-            _ = ticker.tick() => {
-                // Check duration limit
-                if config.spam.duration_seconds > 0 {
-                    if start.elapsed().as_secs() >= config.spam.duration_seconds {
-                        info!("Spam duration completed after {} seconds", config.spam.duration_seconds);
-                        break;
-                    }
+            _ = &mut deadline => {
+                info!("Spam duration completed after {} seconds", config.spam.duration_seconds);
+                break;
+            }
+
+            _ = tokio::signal::ctrl_c() => {
+                info!("Interrupted, shutting down");
+                break;
+            }
+
+            _ = stats_ticker.tick() => {
+                let node = telemetry.borrow().clone();
+
+                let snapshot = stats.snapshot();
+                let sent_since_reset = snapshot.sent - last_sent;
+                last_sent = snapshot.sent;
+
+                let elapsed = stats_start.elapsed().as_secs_f64();
+                let current_tps = if elapsed > 0.0 {
+                    sent_since_reset as f64 / elapsed
+                } else {
+                    0.0
+                };
+
+                let runtime = (start.elapsed() + runtime_offset).as_secs_f64();
+                let achieved_tps = if runtime > 0.0 {
+                    snapshot.sent as f64 / runtime
+                } else {
+                    0.0
+                };
+
+                let (pending, available, invalidations) = shards.iter().fold((0, 0, 0), |(p, a, i), s| {
+                    let shard = lock_shard(s);
+                    (p + shard.pending_count(), a + shard.available_count(), i + shard.invalidations())
+                });
+
+                info!(
+                    "TPS: {:.1} | achieved: {:.1}/{} | sent: {} | failed: {} | mempool: {} | DAA: {} | synced: {} | inflight: {} | buffer: {} | starved: {} | pending: {} | available: {} | invalidated: {} ({} txs) | runtime: {}s",
+                    current_tps,
+                    achieved_tps,
+                    effective_tps,
+                    sent_since_reset,
+                    snapshot.failed,
+                    node.mempool_size,
+                    node.virtual_daa_score,
+                    node.is_synced,
+                    snapshot.inflight,
+                    snapshot.buffered,
+                    snapshot.starved_ticks,
+                    pending,
+                    available,
+                    invalidations,
+                    snapshot.invalidated,
+                    (start.elapsed() + runtime_offset).as_secs()
+                );
+                log_latency(config.spam.mode, &snapshot, config.spam.concurrency);
+
+                if last_10.len() == 10 {
+                    last_10.pop_front();
                 }
+                last_10.push_back(sent_since_reset);
+                let avg_10 = last_10.iter().sum::<u64>() as f64 / last_10.len() as f64;
 
-                // Refresh UTXOs if needed
-                if utxo_manager.needs_refresh(config) {
-                    if let Err(e) = utxo_manager.refresh(&client0, address.clone(), config).await {
-                        warn!("Failed to refresh UTXOs: {}", e);
-                    }
+                debug!(
+                    "TPS Stats - Current: {} | 10s avg: {:.1} | Total sent: {}",
+                    sent_since_reset, avg_10, snapshot.sent
+                );
+                debug!("Sent per worker: {:?}", stats.sent_per_worker());
+
+                ticks += 1;
+                if ticks % POOL_STATS_EVERY == 0 {
+                    info!("Pool [{:?}]: {}", config.utxo.selection, pool_stats(&shards, dust_below));
+                    log_maturing(&maturing, node.virtual_daa_score, &config);
                 }
 
-                // Skip if no UTXOs available or queue is full
-                if utxo_manager.available_count() == 0 {
-                    debug!("No UTXOs available, waiting for refresh");
-                    continue;
+                if node.age_secs().is_some_and(|age| age > 5) {
+                    warn!("Node telemetry is stale ({}s old)", node.age_secs().unwrap_or_default());
                 }
 
-                if inflight.len() >= config.advanced.max_inflight {
-                    debug!("Inflight queue full ({}/{})", inflight.len(), config.advanced.max_inflight);
-                    continue;
+                stats_start = Instant::now();
+            }
+
+            _ = export_ticker.tick(), if export_every > 0 => {
+                if let Some(path) = &export_path {
+                    export_snapshot(&wallet, &config, &shards, &maturing, path).await;
                 }
+            }
+        }
+    }
+
+    for handle in handles {
+        handle.abort();
+    }
+    if let Some(journal) = &journal {
+        if let Err(e) = journal.finish() {
+            warn!("Failed to save run state: {}", e);
+        }
+    }
+    if let Some(path) = &export_path {
+        export_snapshot(&wallet, &config, &shards, &maturing, path).await;
+    }
+    info!("Spam loop completed");
+    Ok(())
+}
 
-                // Calculate how many transactions to send this tick
-                let mut to_send = (target_per_tick + carry).floor() as u64;
-                carry = (target_per_tick + carry) - (to_send as f64);
+/// Captures the pool and writes the snapshot off the async workers
+async fn export_snapshot(
+    wallet: &Wallet,
+    config: &Config,
+    shards: &[UtxoShard],
+    maturing: &SharedMaturity,
+    path: &Path,
+) {
+    let snapshot = UtxoSnapshot::capture(wallet, config, shards, maturing);
+    let path = path.to_path_buf();
+    match tokio::task::spawn_blocking(move || snapshot.save(&path)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Failed to save snapshot: {}", e),
+        Err(e) => warn!("Snapshot save task failed: {}", e),
+    }
+}
 
-                // Limit by available resources
-                to_send = to_send
-                    .min(utxo_manager.available_count() as u64)
-                    .min((config.advanced.max_inflight - inflight.len()) as u64);
+/// Reports the UTXOs waiting to mature, mainly coinbase rewards on a miner's key
+fn log_maturing(maturing: &SharedMaturity, virtual_daa_score: u64, config: &Config) {
+    let report = lock_maturity(maturing).report(virtual_daa_score, config.advanced.coinbase_maturity);
+    if !report.is_empty() {
+        info!("Maturing: {}", report);
+    }
+}
 
-                if to_send == 0 {
-                    continue;
-                }
+/// Worker `id`'s part of `total` split over `workers`, the first ones taking the remainder
+fn share(total: usize, workers: usize, id: usize) -> usize {
+    total / workers + usize::from(id < total % workers)
+}
+
+/// Reports the latencies that matter for each mode
+fn log_latency(mode: SpamMode, snapshot: &StatsSnapshot, concurrency: usize) {
+    let submit = &snapshot.submit_latency;
+    let lag = &snapshot.schedule_lag;
+
+    match mode {
+        SpamMode::Paced => info!(
+            "Latency [paced] submit p50: {:.2}ms p99: {:.2}ms max: {:.2}ms | schedule lag p99: {:.2}ms max: {:.2}ms",
+            submit.percentile_ms(0.5),
+            submit.percentile_ms(0.99),
+            submit.max_ms(),
+            lag.percentile_ms(0.99),
+            lag.max_ms()
+        ),
+        SpamMode::Poisson => info!(
+            "Latency [poisson] submit p50: {:.2}ms p99: {:.2}ms max: {:.2}ms | arrival delay p50: {:.2}ms p99: {:.2}ms max: {:.2}ms",
+            submit.percentile_ms(0.5),
+            submit.percentile_ms(0.99),
+            submit.max_ms(),
+            lag.percentile_ms(0.5),
+            lag.percentile_ms(0.99),
+            lag.max_ms()
+        ),
+        SpamMode::ClosedLoop => info!(
+            "Latency [closed-loop] round-trip mean: {:.2}ms p50: {:.2}ms p99: {:.2}ms max: {:.2}ms | completed: {} | concurrency: {}",
+            submit.mean_ms(),
+            submit.percentile_ms(0.5),
+            submit.percentile_ms(0.99),
+            submit.max_ms(),
+            submit.count(),
+            concurrency
+        ),
+    }
+}
+
+/// Gives each worker a disjoint slice of the client pool, or a shared client
+/// when there are more workers than connections.
+fn client_slice(clients: &[Arc<GrpcClient>], worker: usize, workers: usize) -> Vec<Arc<GrpcClient>> {
+    let slice: Vec<_> = clients
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| i % workers == worker)
+        .map(|(_, c)| c.clone())
+        .collect();
+
+    if slice.is_empty() {
+        vec![clients[worker % clients.len()].clone()]
+    } else {
+        slice
+    }
+}
 
-                // Get batch of UTXOs
-                let batch = utxo_manager.get_batch(to_send as usize);
+/// Keeps a worker's pre-signed transaction buffer topped up from its shard,
+/// so the submitter never waits on signing.
+async fn run_producer(worker: Arc<Worker>, buffer: Sender<SignedTx>) {
+    let config = &worker.config;
+    let mut refill_ticker = interval(Duration::from_millis(config.spam.millis_per_tick));
+    refill_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // Reused across refills so reserving UTXOs does not allocate. The outpoints are
+    // kept aside, since the batch itself is gone if the signing job fails.
+    let mut batch = Vec::with_capacity(config.spam.presign_batch_size);
+    let mut reserved = Vec::with_capacity(config.spam.presign_batch_size);
+
+    loop {
+        refill_ticker.tick().await;
+        if buffer.is_closed() {
+            break;
+        }
+
+        let free = buffer.capacity().min(config.spam.presign_batch_size);
+        if free == 0 {
+            continue;
+        }
 
-                // Build transactions in parallel
-                let transactions = build_spam_transactions(
+        // Reserve a batch of UTXOs
+        {
+            let mut shard = lock_shard(&worker.shard);
+
+            // Prune old pending UTXOs
+            shard.prune_old_pending(config.advanced.max_pending_age_secs, config.utxo.use_notifications);
+
+            if shard.take_batch(free, &mut batch) == 0 {
+                debug!("Worker {}: no UTXOs available, waiting for refresh", worker.id);
+                continue;
+            }
+        }
+
+        let first_seq = worker.next_seq.fetch_add(batch.len() as u64, Ordering::Relaxed);
+        reserved.clear();
+        reserved.extend(batch.iter().map(|(outpoint, _)| *outpoint));
+
+        // Build transactions in parallel on the signing pool, handing the buffer back afterwards
+        let job_worker = worker.clone();
+        let transactions = match worker
+            .signing_pool
+            .run(move || {
+                let (transactions, unbuilt) = build_spam_transactions(
                     &batch,
-                    &address,
-                    &keypair,
-                    config,
+                    &job_worker.wallet,
+                    &job_worker.config,
+                    job_worker.run_id,
+                    first_seq,
                 );
+                batch.clear();
+                (transactions, unbuilt, batch)
+            })
+            .await
+        {
+            Ok((transactions, unbuilt, returned)) => {
+                batch = returned;
+                if !unbuilt.is_empty() {
+                    let mut shard = lock_shard(&worker.shard);
+                    for skipped in &unbuilt {
+                        match skipped {
+                            Unbuilt::Retry(outpoint) => shard.unreserve(outpoint),
+                            Unbuilt::Unusable(outpoint) => shard.release(outpoint, false),
+                        }
+                    }
+                }
+                transactions
+            }
+            Err(e) => {
+                error!("{}", e);
+                let mut shard = lock_shard(&worker.shard);
+                for outpoint in &reserved {
+                    shard.unreserve(outpoint);
+                }
+                batch = Vec::with_capacity(config.spam.presign_batch_size);
+                continue;
+            }
+        };
+
+        // Chain onto our own outputs before they are even submitted
+        if config.utxo.reuse_outputs == ReusePolicy::Immediate {
+            let mut shard = lock_shard(&worker.shard);
+            for (tx, _) in &transactions {
+                let (outpoint, entry) = spam_output(tx);
+                shard.insert_local(outpoint, entry);
+            }
+        }
+
+        // Never blocks: the batch fits in the free capacity checked above
+        for (tx, outpoint) in transactions {
+            if buffer.send(SignedTx { tx, outpoint }).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Pulls pre-signed transactions from the buffer at the worker's paced rate and submits them
+async fn run_submitter(worker: Arc<Worker>, mut buffer: Receiver<SignedTx>) {
+    let config = &worker.config;
+    let stats = worker.stats.worker(worker.id);
+    let mode = config.spam.mode;
+    let reuse = config.utxo.reuse_outputs;
+
+    // Immediately chained transactions may reach the node before their parents
+    let allow_orphan = reuse == ReusePolicy::Immediate;
+
+    // Open-loop modes pace with a token bucket at this worker's share of the rate;
+    // closed-loop mode is limited only by its share of the outstanding submissions
+    let burst = match config.spam.max_burst {
+        0 => Pacer::auto_burst(worker.tps),
+        burst => burst,
+    };
+    let mut pacer = match mode {
+        SpamMode::Paced => Some(Pacer::new(Schedule::Uniform, worker.tps, burst)),
+        SpamMode::Poisson => Some(Pacer::new(Schedule::Poisson, worker.tps, burst)),
+        SpamMode::ClosedLoop => None,
+    };
+    let max_outstanding = match mode {
+        SpamMode::ClosedLoop => worker.concurrency,
+        _ => config.advanced.max_inflight,
+    };
 
-                // Reserve UTXOs
-                utxo_manager.reserve(&batch);
+    // The next transaction to send, pulled ahead so pacing only runs when there is work
+    let mut staged: Option<SignedTx> = None;
 
-                // Submit transactions
-                for (tx, outpoint) in transactions {
-                    let client = clients[round_robin_idx % clients.len()].clone();
+    // Async submit queue
+    let mut inflight: FuturesUnordered<_> = FuturesUnordered::new();
+    let mut round_robin_idx = 0usize;
+
+    loop {
+        let room = max_outstanding.saturating_sub(inflight.len()) as u64;
+
+        tokio::select! {
+            signed = buffer.recv(), if staged.is_none() => {
+                let Some(signed) = signed else {
+                    break;
+                };
+                staged = Some(signed);
+            }
+
+            (tokens, lag) = acquire(pacer.as_mut(), room), if staged.is_some() && room > 0 => {
+                if pacer.is_some() {
+                    stats.schedule_lag.record(lag);
+                }
+
+                // Submit the staged transaction plus whatever else the tokens allow
+                let mut outgoing = Vec::new();
+                while (outgoing.len() as u64) < tokens {
+                    let next = staged.take().or_else(|| buffer.try_recv().ok());
+                    let Some(signed) = next else {
+                        stats.starved_ticks.fetch_add(1, Ordering::Relaxed);
+                        debug!("Worker {}: transaction buffer empty, waiting for producer", worker.id);
+                        break;
+                    };
+                    outgoing.push(signed);
+                }
+
+                // From here on the inputs may be spent on the node even if the call fails.
+                // Children of a failed parent are dropped, and so, in turn, are theirs.
+                {
+                    let mut shard = lock_shard(&worker.shard);
+                    let before = outgoing.len();
+                    outgoing.retain(|signed| {
+                        if shard.take_cancelled(&signed.outpoint) {
+                            shard.discard_local(&spam_output(&signed.tx).0);
+                            return false;
+                        }
+                        shard.mark_attempted(&signed.outpoint);
+                        true
+                    });
+
+                    let cancelled = before - outgoing.len();
+                    if cancelled > 0 {
+                        stats.failed.fetch_add(cancelled as u64, Ordering::Relaxed);
+                        debug!("Worker {}: dropped {} transactions chained on a failed parent", worker.id, cancelled);
+                    }
+                }
+                let sent = outgoing.len() as u64;
+
+                for signed in outgoing {
+                    let client = worker.clients[round_robin_idx % worker.clients.len()].clone();
                     round_robin_idx += 1;
 
                     inflight.push(async move {
+                        let started = Instant::now();
                         let result = client
                             .submit_transaction_call(None, SubmitTransactionRequest {
-                                transaction: RpcTransaction::from(&tx),
-                                allow_orphan: false,
+                                transaction: RpcTransaction::from(&signed.tx),
+                                allow_orphan,
                             })
                             .await;
-                        (result, outpoint)
+                        (result, signed, started.elapsed())
                     });
                 }
+                if let Some(pacer) = pacer.as_mut() {
+                    pacer.refund(tokens - sent);
+                }
 
-                // Prune old pending UTXOs
-                utxo_manager.prune_old_pending(config.advanced.max_pending_age_secs);
+                stats.buffered.store(buffer.len(), Ordering::Relaxed);
+                stats.inflight.store(inflight.len(), Ordering::Relaxed);
             }
 
-            Some((result, outpoint)) = inflight.next() => {
+            Some((result, signed, latency)) = inflight.next() => {
+                stats.submit_latency.record(latency);
                 match result {
                     Ok(_) => {
-                        utxo_manager.mark_spent(outpoint);
-                        sent_since_reset += 1;
-                        let _ = tps_tx.send(1);
+                        let mut shard = lock_shard(&worker.shard);
+                        shard.mark_submitted(&signed.outpoint, signed.tx.id());
+                        match reuse {
+                            ReusePolicy::Accepted => {
+                                let (outpoint, entry) = spam_output(&signed.tx);
+                                shard.insert_local(outpoint, entry);
+                            }
+                            ReusePolicy::Confirmed => {
+                                let (outpoint, entry) = spam_output(&signed.tx);
+                                shard.expect(outpoint, entry);
+                            }
+                            ReusePolicy::Off | ReusePolicy::Immediate => {}
+                        }
+                        drop(shard);
+                        stats.sent.fetch_add(1, Ordering::Relaxed);
                     }
                     Err(e) => {
-                        utxo_manager.release(&outpoint);
-                        debug!("Transaction submission failed: {}", e);
+                        let mut shard = lock_shard(&worker.shard);
+                        // The spend may still have reached the node, so with reconciliation
+                        // the outpoint stays reserved until the node's state settles it
+                        if config.advanced.reconcile_interval_secs == 0 {
+                            shard.release(&signed.outpoint, config.utxo.use_notifications);
+                        }
+                        if reuse == ReusePolicy::Immediate {
+                            shard.discard_local(&spam_output(&signed.tx).0);
+                        }
+                        let invalidated = shard.take_invalidated(&signed.outpoint);
+                        drop(shard);
+                        stats.failed.fetch_add(1, Ordering::Relaxed);
+                        if invalidated {
+                            stats.invalidated.fetch_add(1, Ordering::Relaxed);
+                            warn!(
+                                "Transaction {} failed: its input {} was removed from the UTXO set: {}",
+                                signed.tx.id(),
+                                signed.outpoint,
+                                e
+                            );
+                        } else {
+                            debug!("Transaction submission failed: {}", e);
+                        }
                     }
                 }
-            }
-
-            _ = stats_ticker.tick() => {
-                let mempool_size = client0.get_info().await
-                    .map(|i| i.mempool_size)
-                    .unwrap_or(0);
-
-                let elapsed = stats_start.elapsed().as_secs_f64();
-                let current_tps = if elapsed > 0.0 {
-                    sent_since_reset as f64 / elapsed
-                } else {
-                    0.0
-                };
-
-                info!(
-                    "TPS: {:.1} | sent: {} | mempool: {} | inflight: {} | pending: {} | available: {} | runtime: {}s",
-                    current_tps,
-                    sent_since_reset,
-                    mempool_size,
-                    inflight.len(),
-                    utxo_manager.pending.len(),
-                    utxo_manager.available_count(),
-                    start.elapsed().as_secs()
-                );
-
-                stats_start = Instant::now();
-                sent_since_reset = 0;
+                stats.inflight.store(inflight.len(), Ordering::Relaxed);
             }
         }
     }
+}
 
-    info!("Spam loop completed");
-    Ok(())
+/// Takes pacing tokens, or all of `room` at once when there is no pacer (closed loop)
+async fn acquire(pacer: Option<&mut Pacer>, room: u64) -> (u64, Duration) {
+    match pacer {
+        Some(pacer) => pacer.acquire(room).await,
+        None => (room, Duration::ZERO),
+    }
 }
 
+/// Signs a spam transaction for each reserved UTXO, setting aside those that yield none
 fn build_spam_transactions(
     batch: &[(TransactionOutpoint, CoreUtxoEntry)],
-    address: &Address,
-    keypair: &Arc<Keypair>,
+    wallet: &Wallet,
     config: &Config,
-) -> Vec<(Transaction, TransactionOutpoint)> {
+    run_id: Option<u64>,
+    first_seq: u64,
+) -> (Vec<(Transaction, TransactionOutpoint)>, Vec<Unbuilt>) {
     batch
         .par_iter()
-        .filter_map(|(outpoint, entry)| {
-            let fee = calculate_fee(config, 1, 1, false);
+        .enumerate()
+        .partition_map(|(i, (outpoint, entry))| {
+            let Some(key) = wallet.key_for(&entry.script_public_key) else {
+                error!("No wallet key can spend UTXO {}", outpoint);
+                return Either::Right(Unbuilt::Unusable(*outpoint));
+            };
+
+            let payload = run_id
+                .map(|id| encode_run_payload(id, first_seq + i as u64))
+                .unwrap_or_default();
+
+            let fee = calculate_fee(config, 1, 1, payload.len(), false);
             let output_amount = entry.amount.saturating_sub(fee);
 
             if output_amount < config.utxo.min_change_sompi {
-                debug!("Skipping UTXO with insufficient value after fee");
-                return None;
+                debug!("Dropping UTXO {} with insufficient value after fee", outpoint);
+                return Either::Right(Unbuilt::Unusable(*outpoint));
             }
 
+            // Pay back to the same address so the output stays with its key
             match create_spam_transaction(
-                keypair,
+                &key.keypair,
                 *outpoint,
                 entry.clone(),
                 output_amount,
-                address,
+                &key.address,
+                payload,
             ) {
-                Ok(tx) => Some((tx, *outpoint)),
+                Ok(tx) => Either::Left((tx, *outpoint)),
                 Err(e) => {
                     error!("Failed to create transaction: {}", e);
-                    None
+                    Either::Right(Unbuilt::Retry(*outpoint))
                 }
             }
         })
-        .collect()
 }
 
-fn spawn_tps_logger() -> UnboundedSender<u32> {
-    let (tx, mut rx) = unbounded_channel::<u32>();
-
-    tokio::spawn(async move {
-        let mut per_second = 0u64;
-        let mut total = 0u64;
-        let mut last_10: std::collections::VecDeque<u64> =
-            std::collections::VecDeque::with_capacity(10);
-        let mut ticker = interval(Duration::from_secs(1));
-
-        loop {
-            tokio::select! {
-                Some(n) = rx.recv() => {
-                    per_second += n as u64;
-                    total += n as u64;
-                }
-                _ = ticker.tick() => {
-                    if last_10.len() == 10 {
-                        last_10.pop_front();
-                    }
-                    last_10.push_back(per_second);
-
-                    let avg_10 = if last_10.is_empty() {
-                        0.0
-                    } else {
-                        last_10.iter().sum::<u64>() as f64 / last_10.len() as f64
-                    };
-
-                    debug!(
-                        "TPS Stats - Current: {} | 10s avg: {:.1} | Total sent: {}",
-                        per_second, avg_10, total
-                    );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                    per_second = 0;
-                }
-            }
-        }
-    });
-
-    tx
-}
\ No newline at end of file
+    #[test]
+    fn shares_add_up_with_the_remainder_up_front() {
+        let shares = |total, workers| (0..workers).map(|id| share(total, workers, id)).collect::<Vec<_>>();
+        assert_eq!(shares(10, 4), vec![3, 3, 2, 2]);
+        assert_eq!(shares(2, 3), vec![1, 1, 0]);
+        assert_eq!(share(7, 1, 0), 7);
+    }
+}
@@ -0,0 +1,841 @@
+use crate::config::Config;
+use crate::error::{Result, TxGenError};
+use crate::signer::SigningPool;
+use crate::transaction::{calculate_fee, create_fanout_transaction, SOMPI_PER_KAS};
+use crate::utxo::{lock_shard, UtxoShard};
+use crate::wallet::Wallet;
+use futures::stream::{self, StreamExt};
+use kaspa_consensus_core::tx::{
+    ScriptPublicKey, Transaction, TransactionId, TransactionOutpoint, UtxoEntry as CoreUtxoEntry,
+};
+use kaspa_grpc_client::GrpcClient;
+use kaspa_rpc_core::{api::rpc::RpcApi, model::SubmitTransactionRequest, RpcTransaction};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep, MissedTickBehavior};
+use tracing::{error, info, warn};
+
+/// Submissions kept outstanding per client while a tree level is sent
+const SUBMITS_PER_CLIENT: usize = 16;
+
+/// Span of recent levels the ETA's submission rate is measured over
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+type Utxo = (TransactionOutpoint, CoreUtxoEntry);
+
+/// One UTXO to be split into `leaves` outputs of the plan's amount
+#[derive(Clone)]
+pub struct SplitNode {
+    pub utxo: Utxo,
+    pub leaves: usize,
+}
+
+/// Shape of the splitting tree. Every transaction fans out to at most `fanout`
+/// subtrees; intermediate outputs carry exactly the value their subtree needs,
+/// so only roots produce change.
+#[derive(Debug, Clone)]
+pub struct SplitPlan {
+    amount: u64,
+    fanout: usize,
+    fee: u64,
+    min_change: u64,
+}
+
+impl SplitPlan {
+    pub fn new(config: &Config, amount: u64) -> Self {
+        let fanout = config.utxo.outputs_per_transaction.max(2);
+        Self {
+            amount,
+            fanout,
+            fee: calculate_fee(config, 1, fanout + 1, 0, true),
+            min_change: config.utxo.min_change_sompi,
+        }
+    }
+
+    /// Subtree sizes a node holding `leaves` leaves fans out to, as evenly as possible
+    fn children(&self, leaves: usize) -> Vec<usize> {
+        let branches = self.fanout.min(leaves);
+        let base = leaves / branches;
+        let extra = leaves % branches;
+        (0..branches).map(|i| base + usize::from(i < extra)).collect()
+    }
+
+    /// Value an output must carry to fund `leaves` leaves and every fee below it
+    pub fn subtree_value(&self, leaves: usize) -> u64 {
+        match leaves {
+            0 => 0,
+            1 => self.amount,
+            _ => self.fold_children(leaves, |n| self.subtree_value(n)) + self.fee,
+        }
+    }
+
+    /// Transactions needed to split one output into `leaves` leaves
+    pub fn subtree_txs(&self, leaves: usize) -> usize {
+        match leaves {
+            0 | 1 => 0,
+            _ => self.fold_children(leaves, |n| self.subtree_txs(n) as u64) as usize + 1,
+        }
+    }
+
+    /// Tree levels needed to split one output into `leaves` leaves
+    pub fn depth(&self, leaves: usize) -> usize {
+        match leaves {
+            0 | 1 => 0,
+            _ => 1 + self.depth(leaves.div_ceil(self.fanout)),
+        }
+    }
+
+    /// Sums `f` over the children of a node. Children only come in two sizes,
+    /// so each size is evaluated once.
+    fn fold_children(&self, leaves: usize, f: impl Fn(usize) -> u64) -> u64 {
+        let branches = self.fanout.min(leaves) as u64;
+        let base = leaves / branches as usize;
+        let extra = (leaves % branches as usize) as u64;
+
+        let mut total = (branches - extra) * f(base);
+        if extra > 0 {
+            total += extra * f(base + 1);
+        }
+        total
+    }
+
+    /// Most leaves, up to `max_leaves`, that a UTXO of `amount` sompi can fund
+    pub fn capacity(&self, amount: u64, max_leaves: usize) -> usize {
+        let (mut lo, mut hi) = (0usize, max_leaves);
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            if self.subtree_value(mid) <= amount {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        lo
+    }
+
+    /// Leaves each root funds when `needed` leaves are handed out over `amounts`, sorted
+    /// largest first. A UTXO is only used as a root if it can fund at least two leaves,
+    /// so roots are always a prefix of `amounts`.
+    fn allocate(&self, amounts: impl IntoIterator<Item = u64>, needed: usize) -> Vec<usize> {
+        let mut remaining = needed;
+        let mut allocation = Vec::new();
+        for amount in amounts {
+            if remaining == 0 {
+                break;
+            }
+
+            let leaves = self.capacity(amount, remaining);
+            if leaves < 2 {
+                break;
+            }
+            remaining -= leaves;
+            allocation.push(leaves);
+        }
+
+        allocation
+    }
+
+    /// Assigns `needed` leaves to roots, largest UTXO first, taking the roots out of `utxos`
+    pub fn assign_roots(&self, utxos: &mut Vec<Utxo>, needed: usize) -> Vec<SplitNode> {
+        utxos.sort_by(|a, b| b.1.amount.cmp(&a.1.amount));
+
+        let allocation = self.allocate(utxos.iter().map(|(_, entry)| entry.amount), needed);
+        utxos
+            .drain(..allocation.len())
+            .zip(allocation)
+            .map(|(utxo, leaves)| SplitNode { utxo, leaves })
+            .collect()
+    }
+
+    /// Change left on a root of `amount` after funding `leaves` leaves, if worth keeping
+    fn change(&self, amount: u64, leaves: usize) -> Option<u64> {
+        let change = amount.saturating_sub(self.subtree_value(leaves));
+        (change >= self.min_change).then_some(change)
+    }
+
+    /// Output values for one node: one per subtree, plus change if it is worth keeping
+    fn outputs(&self, node: &SplitNode) -> Vec<(u64, usize)> {
+        let mut outputs: Vec<_> = self
+            .children(node.leaves)
+            .into_iter()
+            .map(|leaves| (self.subtree_value(leaves), leaves))
+            .collect();
+
+        if let Some(change) = self.change(node.utxo.1.amount, node.leaves) {
+            outputs.push((change, 0));
+        }
+        outputs
+    }
+}
+
+/// Expected outcome of splitting into one denomination
+#[derive(Debug, Default)]
+pub struct TierForecast {
+    pub amount: u64,
+    pub wanted: usize,
+    pub planned: usize,
+    pub roots: usize,
+    pub transactions: usize,
+    pub depth: usize,
+}
+
+/// Splitting plan over a mix of UTXO sizes. Denominations are split one after another,
+/// largest first, each into its share of the needed UTXOs; the change of one
+/// denomination's roots is available to the next.
+pub struct DenominationPlan {
+    tiers: Vec<(SplitPlan, u32)>,
+}
+
+impl DenominationPlan {
+    pub fn new(config: &Config) -> Self {
+        let mut tiers: Vec<_> = if config.utxo.denominations.is_empty() {
+            vec![(SplitPlan::new(config, config.utxo.amount_per_utxo), 1)]
+        } else {
+            config
+                .utxo
+                .denominations
+                .iter()
+                .map(|d| (SplitPlan::new(config, d.amount), d.share))
+                .collect()
+        };
+        tiers.sort_by(|a, b| b.0.amount.cmp(&a.0.amount));
+        Self { tiers }
+    }
+
+    /// Divides `needed` UTXOs between the denominations by share
+    fn counts(&self, needed: usize) -> Vec<usize> {
+        let total_share: u64 = self.tiers.iter().map(|(_, share)| *share as u64).sum();
+        let mut counts: Vec<usize> = self
+            .tiers
+            .iter()
+            .map(|(_, share)| (needed as u64 * *share as u64 / total_share) as usize)
+            .collect();
+
+        // Rounding leaves fewer than one UTXO per denomination over
+        let rest = needed - counts.iter().sum::<usize>();
+        for count in counts.iter_mut().take(rest) {
+            *count += 1;
+        }
+        counts
+    }
+
+    /// Smallest UTXO worth splitting under any denomination
+    pub fn min_root(&self) -> u64 {
+        self.tiers.iter().map(|(plan, _)| plan.subtree_value(2)).min().unwrap_or(u64::MAX)
+    }
+
+    /// What splitting UTXOs of the given amounts into `needed` UTXOs would produce
+    pub fn forecast(&self, amounts: &[u64], needed: usize) -> Vec<TierForecast> {
+        self.forecast_roots(amounts, needed).0
+    }
+
+    /// The forecast together with the indices into `amounts` of the UTXOs it uses as
+    /// roots. Change carried between denominations is not among them.
+    pub fn forecast_roots(&self, amounts: &[u64], needed: usize) -> (Vec<TierForecast>, Vec<usize>) {
+        // Each amount with its index in `amounts`; change has none
+        let mut pool: Vec<(u64, Option<usize>)> = amounts.iter().copied().zip((0..).map(Some)).collect();
+        let mut forecasts = Vec::with_capacity(self.tiers.len());
+        let mut roots = Vec::new();
+
+        for ((plan, _), wanted) in self.tiers.iter().zip(self.counts(needed)) {
+            pool.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+            let allocation = plan.allocate(pool.iter().map(|&(amount, _)| amount), wanted);
+            let change: Vec<(u64, Option<usize>)> = pool
+                .iter()
+                .zip(&allocation)
+                .filter_map(|(&(amount, _), &leaves)| plan.change(amount, leaves))
+                .map(|change| (change, None))
+                .collect();
+
+            forecasts.push(TierForecast {
+                amount: plan.amount,
+                wanted,
+                planned: allocation.iter().sum(),
+                roots: allocation.len(),
+                transactions: allocation.iter().map(|&leaves| plan.subtree_txs(leaves)).sum(),
+                depth: allocation.iter().map(|&leaves| plan.depth(leaves)).max().unwrap_or(0),
+            });
+
+            roots.extend(pool.drain(..allocation.len()).filter_map(|(_, index)| index));
+            pool.extend(change);
+        }
+
+        (forecasts, roots)
+    }
+
+    /// Most UTXOs the given amounts can be fully split into under this mix
+    pub fn max_count(&self, amounts: &[u64]) -> usize {
+        let total: u64 = amounts.iter().sum();
+        let smallest = self.tiers.iter().map(|(plan, _)| plan.amount).min().unwrap_or(1);
+
+        let (mut lo, mut hi) = (0usize, (total / smallest) as usize);
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            if self.forecast(amounts, mid).iter().all(|tier| tier.planned == tier.wanted) {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        lo
+    }
+}
+
+/// Outcome of a splitting run
+#[derive(Debug, Default)]
+pub struct SplitReport {
+    pub transactions: usize,
+    pub failed: usize,
+    pub created: usize,
+    pub elapsed: Duration,
+    /// Roots whose first-level transaction was accepted, with its id
+    pub spent_roots: Vec<(TransactionOutpoint, TransactionId)>,
+    /// Change outputs of those root transactions
+    pub change: Vec<Utxo>,
+}
+
+impl SplitReport {
+    fn absorb(&mut self, other: SplitReport) {
+        self.transactions += other.transactions;
+        self.failed += other.failed;
+        self.created += other.created;
+        self.spent_roots.extend(other.spent_roots);
+        self.change.extend(other.change);
+    }
+}
+
+/// Callback for root outpoints whose spends are about to reach the node
+pub type RootsSubmitted<'a> = dyn Fn(&[TransactionOutpoint]) + Sync + 'a;
+
+/// Splits every root into its leaves as a fan-out tree, one level at a time. Each level
+/// is signed in parallel and submitted concurrently as soon as the level above has been
+/// accepted, so the run takes a handful of round-trips instead of one per transaction.
+/// New UTXOs are spread over the wallet's addresses; change returns to the spent address.
+/// `on_submit` is handed the root outpoints right before the first level goes out.
+/// The report comes back even if a level fails to sign, so the roots already spent
+/// are known.
+pub async fn split_tree(
+    clients: &[Arc<GrpcClient>],
+    signing_pool: &SigningPool,
+    wallet: &Arc<Wallet>,
+    plan: &SplitPlan,
+    roots: Vec<SplitNode>,
+    on_submit: &RootsSubmitted<'_>,
+) -> (SplitReport, Result<()>) {
+    let started = Instant::now();
+    let total_txs: usize = roots.iter().map(|root| plan.subtree_txs(root.leaves)).sum();
+    let submit_concurrency = clients.len() * SUBMITS_PER_CLIENT;
+
+    let mut report = SplitReport::default();
+    let mut level = roots;
+    let mut depth = 0usize;
+    // Transactions done as each level finished, for the recent submission rate
+    let mut progress = VecDeque::from([(started, 0usize)]);
+
+    while !level.is_empty() {
+        depth += 1;
+
+        // Sign the whole level on the signing pool
+        let job_plan = plan.clone();
+        let job_wallet = wallet.clone();
+        let signed: Result<Vec<(Transaction, Vec<(u64, usize)>)>> = signing_pool
+            .run(move || {
+                level
+                    .par_iter()
+                    .filter_map(|node| {
+                        let Some(key) = job_wallet.key_for(&node.utxo.1.script_public_key) else {
+                            error!("No wallet key can spend splitting input {}", node.utxo.0);
+                            return None;
+                        };
+                        let outputs = job_plan.outputs(node);
+                        let destinations: Vec<(u64, ScriptPublicKey)> = outputs
+                            .iter()
+                            .map(|&(value, leaves)| {
+                                let script = match leaves {
+                                    0 => node.utxo.1.script_public_key.clone(),
+                                    _ => job_wallet.next_destination().script_public_key.clone(),
+                                };
+                                (value, script)
+                            })
+                            .collect();
+                        match create_fanout_transaction(&key.keypair, &node.utxo, &destinations) {
+                            Ok(tx) => Some((tx, outputs)),
+                            Err(e) => {
+                                error!("Failed to create splitting transaction: {}", e);
+                                None
+                            }
+                        }
+                    })
+                    .collect()
+            })
+            .await;
+        let signed = match signed {
+            Ok(signed) => signed,
+            Err(e) => {
+                report.elapsed = started.elapsed();
+                return (report, Err(e));
+            }
+        };
+
+        if depth == 1 {
+            on_submit(&signed.iter().map(|(tx, _)| tx.inputs[0].previous_outpoint).collect::<Vec<_>>());
+        }
+
+        // Submit it concurrently across the client pool
+        let results: Vec<_> = stream::iter(signed.into_iter().enumerate())
+            .map(|(i, (tx, outputs))| {
+                let client = clients[i % clients.len()].clone();
+                async move {
+                    let result = client
+                        .submit_transaction_call(None, SubmitTransactionRequest {
+                            transaction: RpcTransaction::from(&tx),
+                            allow_orphan: true,
+                        })
+                        .await;
+                    (result, tx, outputs)
+                }
+            })
+            .buffer_unordered(submit_concurrency.max(1))
+            .collect()
+            .await;
+
+        // Outputs of accepted transactions that still need splitting form the next level
+        let mut next = Vec::new();
+        for (result, tx, outputs) in results {
+            if let Err(e) = result {
+                report.failed += 1;
+                warn!("Splitting transaction {} failed: {}", tx.id(), e);
+                continue;
+            }
+            report.transactions += 1;
+            if depth == 1 {
+                report.spent_roots.push((tx.inputs[0].previous_outpoint, tx.id()));
+            }
+
+            for (index, (value, leaves)) in outputs.into_iter().enumerate() {
+                let utxo = (
+                    TransactionOutpoint::new(tx.id(), index as u32),
+                    CoreUtxoEntry::new(value, tx.outputs[index].script_public_key.clone(), 0, false),
+                );
+                match leaves {
+                    1 => report.created += 1,
+                    0 => report.change.push(utxo),
+                    _ => next.push(SplitNode { utxo, leaves }),
+                }
+            }
+        }
+
+        // Levels grow with depth, so the rate of the last few predicts the rest
+        // better than the average since the start
+        let done = report.transactions + report.failed;
+        let now = Instant::now();
+        progress.push_back((now, done));
+        while progress.len() > 2 && now.duration_since(progress[1].0) >= RATE_WINDOW {
+            progress.pop_front();
+        }
+        let (since, done_then) = progress[0];
+        let rate = (done - done_then) as f64 / now.duration_since(since).as_secs_f64().max(f64::EPSILON);
+        let eta = if rate > 0.0 {
+            Duration::from_secs_f64(total_txs.saturating_sub(done) as f64 / rate)
+        } else {
+            Duration::ZERO
+        };
+        info!(
+            "Splitting level {}: {}/{} transactions, {} failed, {} UTXOs created | {:.1} tx/s | elapsed: {:.1}s | ETA: {:.1}s",
+            depth,
+            done,
+            total_txs,
+            report.failed,
+            report.created,
+            rate,
+            now.duration_since(started).as_secs_f64(),
+            eta.as_secs_f64()
+        );
+
+        level = next;
+    }
+
+    report.elapsed = started.elapsed();
+    (report, Ok(()))
+}
+
+/// Splits `utxos` into `needed` new UTXOs, one denomination after another. Change from
+/// each denomination's roots is fed to the next, so one large UTXO can fund the whole mix.
+/// Like [`split_tree`], it returns what was done alongside any error.
+pub async fn split_denominations(
+    clients: &[Arc<GrpcClient>],
+    signing_pool: &SigningPool,
+    wallet: &Arc<Wallet>,
+    plan: &DenominationPlan,
+    utxos: Vec<Utxo>,
+    needed: usize,
+    on_submit: &RootsSubmitted<'_>,
+) -> (SplitReport, Result<()>) {
+    let started = Instant::now();
+    let mut pool = utxos;
+    let mut total = SplitReport::default();
+
+    for ((tier, _), wanted) in plan.tiers.iter().zip(plan.counts(needed)) {
+        let roots = tier.assign_roots(&mut pool, wanted);
+        if roots.is_empty() {
+            continue;
+        }
+
+        info!("Splitting {} roots into {:.4} KAS UTXOs", roots.len(), tier.amount as f64 / SOMPI_PER_KAS);
+        let (mut report, result) = split_tree(clients, signing_pool, wallet, tier, roots, on_submit).await;
+        pool.append(&mut report.change);
+        total.absorb(report);
+        if result.is_err() {
+            total.elapsed = started.elapsed();
+            return (total, result);
+        }
+    }
+
+    total.elapsed = started.elapsed();
+    (total, Ok(()))
+}
+
+/// Pool size the balance supports under the denomination plan: the existing UTXOs plus
+/// every UTXO splitting them can create
+pub fn auto_target_count(utxos: &[Utxo], config: &Config) -> usize {
+    let amounts: Vec<u64> = utxos.iter().map(|(_, entry)| entry.amount).collect();
+    utxos.len() + DenominationPlan::new(config).max_count(&amounts)
+}
+
+fn log_forecast(forecast: &[TierForecast]) {
+    info!("Splitting plan:");
+    for tier in forecast {
+        info!(
+            "  {:>10.4} KAS x {:<6} (wanted {}) from {} roots in {} transactions over {} levels",
+            tier.amount as f64 / SOMPI_PER_KAS,
+            tier.planned,
+            tier.wanted,
+            tier.roots,
+            tier.transactions,
+            tier.depth
+        );
+    }
+    info!(
+        "  total: {} UTXOs in {} transactions",
+        forecast.iter().map(|tier| tier.planned).sum::<usize>(),
+        forecast.iter().map(|tier| tier.transactions).sum::<usize>()
+    );
+}
+
+/// Splits the wallet's UTXOs up to `target_utxo_count` following the denomination plan,
+/// after showing what the balance can actually produce
+pub async fn perform_utxo_splitting(
+    clients: &[Arc<GrpcClient>],
+    wallet: &Arc<Wallet>,
+    utxos: Vec<Utxo>,
+    config: &Config,
+) -> Result<()> {
+    info!("=== Phase 1: UTXO Splitting ===");
+
+    let utxos_needed = config.utxo.target_utxo_count.saturating_sub(utxos.len());
+    info!("Need to create {} more UTXOs", utxos_needed);
+
+    let plan = DenominationPlan::new(config);
+    let amounts: Vec<u64> = utxos.iter().map(|(_, entry)| entry.amount).collect();
+    let forecast = plan.forecast(&amounts, utxos_needed);
+    log_forecast(&forecast);
+
+    let planned: usize = forecast.iter().map(|tier| tier.planned).sum();
+    if planned == 0 {
+        return Err(TxGenError::InsufficientFunds {
+            required: plan.min_root() as f64 / SOMPI_PER_KAS,
+            available: amounts.iter().max().copied().unwrap_or(0) as f64 / SOMPI_PER_KAS,
+        });
+    }
+    if planned < utxos_needed {
+        warn!("Balance only covers {} of the {} UTXOs needed", planned, utxos_needed);
+    }
+
+    let signing_pool = SigningPool::new(config.advanced.signing_threads)?;
+    let (report, result) =
+        split_denominations(clients, &signing_pool, wallet, &plan, utxos, utxos_needed, &|_| {}).await;
+    result?;
+
+    info!(
+        "Created {} UTXOs with {} splitting transactions ({} failed) in {:.1}s, waiting for confirmations...",
+        report.created,
+        report.transactions,
+        report.failed,
+        report.elapsed.as_secs_f64()
+    );
+    sleep(Duration::from_secs(10)).await;
+
+    Ok(())
+}
+
+/// Keeps the pool topped up during spam. When available UTXOs drop below the low-water
+/// mark, the large ones the plan picks as roots are reserved out of the shards, so
+/// workers cannot spend them meanwhile, and split back up to `target_utxo_count`. The new UTXOs reach the shards
+/// through the refresher like any other incoming UTXO.
+pub fn spawn_replenisher(
+    shards: Vec<UtxoShard>,
+    clients: Vec<Arc<GrpcClient>>,
+    signing_pool: Arc<SigningPool>,
+    wallet: Arc<Wallet>,
+    config: Arc<Config>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let plan = DenominationPlan::new(&config);
+        let target = config.utxo.target_utxo_count;
+        let low_water = match config.utxo.replenish_below {
+            0 => target / 2,
+            below => below,
+        };
+        let cooldown = Duration::from_secs(config.utxo.replenish_cooldown_secs);
+
+        // Anything that can fund two UTXOs of some denomination is worth splitting
+        let min_root = plan.min_root();
+
+        let mut ticker = interval(Duration::from_secs(1));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut warned = false;
+
+        loop {
+            ticker.tick().await;
+
+            let (available, balance) = shards.iter().fold((0usize, 0u64), |(count, balance), s| {
+                let shard = lock_shard(s);
+                (count + shard.available_count(), balance + shard.total_balance())
+            });
+            if available >= low_water {
+                warned = false;
+                continue;
+            }
+            let needed = target.saturating_sub(available);
+
+            let mut candidates = Vec::new();
+            let mut owners = HashMap::new();
+            for (i, shard) in shards.iter().enumerate() {
+                let mut taken = Vec::new();
+                lock_shard(shard).take_large(min_root, &mut taken);
+                owners.extend(taken.iter().map(|(op, _)| (*op, i)));
+                candidates.append(&mut taken);
+            }
+
+            // Candidates the plan does not split go back right away, so spam can use them
+            // while the tree is built
+            let amounts: Vec<u64> = candidates.iter().map(|(_, entry)| entry.amount).collect();
+            let (forecast, picked) = plan.forecast_roots(&amounts, needed);
+            let picked: HashSet<usize> = picked.into_iter().collect();
+            let mut roots = Vec::with_capacity(picked.len());
+            for (i, utxo) in candidates.into_iter().enumerate() {
+                if picked.contains(&i) {
+                    roots.push(utxo);
+                } else if let Some(shard) = owners.remove(&utxo.0) {
+                    lock_shard(&shards[shard]).unreserve(&utxo.0);
+                }
+            }
+
+            let planned: usize = forecast.iter().map(|tier| tier.planned).sum();
+            if planned == 0 {
+                if !warned {
+                    warn!(
+                        "UTXO pool is low ({} available, {:.2} KAS) and no UTXO is large enough to split",
+                        available,
+                        balance as f64 / SOMPI_PER_KAS
+                    );
+                    warned = true;
+                }
+                continue;
+            }
+
+            info!(
+                "Replenishing UTXO pool: {} available (low water {}), splitting {} roots into {} UTXOs",
+                available,
+                low_water,
+                roots.len(),
+                planned
+            );
+            if planned < needed {
+                warn!("Balance only covers {} of the {} UTXOs needed to reach the target", planned, needed);
+            }
+
+            // Roots stay reserved until the tree is done, but are spent from the moment
+            // their transactions go out
+            let mark_attempted = |roots: &[TransactionOutpoint]| {
+                for op in roots {
+                    if let Some(&shard) = owners.get(op) {
+                        lock_shard(&shards[shard]).mark_attempted(op);
+                    }
+                }
+            };
+
+            let (report, result) =
+                split_denominations(&clients, &signing_pool, &wallet, &plan, roots, needed, &mark_attempted)
+                    .await;
+
+            // Roots spent before a failure are gone just the same. Later denominations
+            // may split change outputs, which no shard owns.
+            let mut spent = HashSet::new();
+            for (op, txid) in &report.spent_roots {
+                if let Some(&shard) = owners.get(op) {
+                    lock_shard(&shards[shard]).mark_submitted(op, *txid);
+                    spent.insert(*op);
+                }
+            }
+            match result {
+                Ok(()) => info!(
+                    "Replenished {} UTXOs with {} transactions ({} failed) in {:.1}s",
+                    report.created,
+                    report.transactions,
+                    report.failed,
+                    report.elapsed.as_secs_f64()
+                ),
+                Err(e) => warn!(
+                    "Failed to replenish UTXO pool after {} transactions and {} UTXOs: {}",
+                    report.transactions,
+                    report.created,
+                    e
+                ),
+            }
+
+            // Candidates that were not split go back to the pool
+            for (op, &shard) in &owners {
+                if !spent.contains(op) {
+                    lock_shard(&shards[shard]).unreserve(op);
+                }
+            }
+
+            // Give the new UTXOs time to confirm and reach the shards
+            sleep(cooldown).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::utxo;
+
+    fn plan(amount: u64) -> SplitPlan {
+        SplitPlan { amount, fanout: 3, fee: 10, min_change: 50 }
+    }
+
+    #[test]
+    fn children_split_leaves_evenly() {
+        let plan = plan(1000);
+        assert_eq!(plan.children(7), vec![3, 2, 2]);
+        assert_eq!(plan.children(9), vec![3, 3, 3]);
+        assert_eq!(plan.children(2), vec![1, 1]);
+    }
+
+    #[test]
+    fn subtree_sizes() {
+        let plan = plan(1000);
+        assert_eq!(plan.subtree_value(1), 1000);
+        assert_eq!(plan.subtree_value(2), 2010);
+        assert_eq!(plan.subtree_value(4), 4020);
+        assert_eq!(plan.subtree_value(9), 9040);
+
+        assert_eq!(plan.subtree_txs(1), 0);
+        assert_eq!(plan.subtree_txs(3), 1);
+        assert_eq!(plan.subtree_txs(4), 2);
+        assert_eq!(plan.subtree_txs(9), 4);
+
+        assert_eq!(plan.depth(1), 0);
+        assert_eq!(plan.depth(3), 1);
+        assert_eq!(plan.depth(9), 2);
+        assert_eq!(plan.depth(10), 3);
+    }
+
+    #[test]
+    fn subtree_sizes_match_the_tree_walk() {
+        fn walk(plan: &SplitPlan, leaves: usize) -> (u64, usize) {
+            if leaves <= 1 {
+                return (plan.amount * leaves as u64, 0);
+            }
+            plan.children(leaves).into_iter().fold((plan.fee, 1), |(value, txs), child| {
+                let (child_value, child_txs) = walk(plan, child);
+                (value + child_value, txs + child_txs)
+            })
+        }
+
+        let plan = plan(1000);
+        for leaves in 0..200 {
+            let expected = (plan.subtree_value(leaves), plan.subtree_txs(leaves));
+            assert_eq!(walk(&plan, leaves), expected, "{} leaves", leaves);
+        }
+    }
+
+    #[test]
+    fn capacity_is_the_most_leaves_the_amount_funds() {
+        let plan = plan(1000);
+        assert_eq!(plan.capacity(4020, 100), 4);
+        assert_eq!(plan.capacity(4019, 100), 3);
+        assert_eq!(plan.capacity(999, 100), 0);
+        assert_eq!(plan.capacity(1_000_000, 5), 5);
+    }
+
+    #[test]
+    fn allocate_stops_at_roots_that_cannot_fund_two_leaves() {
+        let plan = plan(1000);
+        assert_eq!(plan.allocate([4020, 2010, 1500], 10), vec![4, 2]);
+        assert_eq!(plan.allocate([4020, 2010], 5), vec![4]);
+        assert_eq!(plan.allocate([4020, 2010], 6), vec![4, 2]);
+    }
+
+    #[test]
+    fn assign_roots_takes_the_largest_utxos() {
+        let plan = plan(1000);
+        let mut utxos = vec![utxo(1, 2010), utxo(2, 4020), utxo(3, 100)];
+
+        let roots = plan.assign_roots(&mut utxos, 6);
+        let assigned: Vec<_> = roots.iter().map(|root| (root.utxo.1.amount, root.leaves)).collect();
+        assert_eq!(assigned, vec![(4020, 4), (2010, 2)]);
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].1.amount, 100);
+    }
+
+    #[test]
+    fn root_outputs_carry_subtrees_and_change() {
+        let plan = plan(1000);
+        assert_eq!(plan.change(4100, 4), Some(80));
+        assert_eq!(plan.change(4050, 4), None);
+
+        let node = SplitNode { utxo: utxo(1, 4100), leaves: 4 };
+        assert_eq!(plan.outputs(&node), vec![(2010, 2), (1000, 1), (1000, 1), (80, 0)]);
+    }
+
+    #[test]
+    fn counts_follow_shares() {
+        let mix = DenominationPlan { tiers: vec![(plan(1000), 3), (plan(100), 1)] };
+        assert_eq!(mix.counts(10), vec![8, 2]);
+        assert_eq!(mix.counts(4), vec![3, 1]);
+        assert_eq!(mix.min_root(), 210);
+    }
+
+    #[test]
+    fn forecast_carries_change_to_the_next_denomination() {
+        let mix = DenominationPlan { tiers: vec![(plan(1000), 1), (plan(100), 1)] };
+
+        let forecast = mix.forecast(&[10_000], 4);
+        assert_eq!(forecast.len(), 2);
+        for tier in &forecast {
+            assert_eq!((tier.wanted, tier.planned, tier.roots, tier.transactions, tier.depth), (2, 2, 1, 1, 1));
+        }
+    }
+
+    #[test]
+    fn forecast_roots_lists_only_the_utxos_split() {
+        let mix = DenominationPlan { tiers: vec![(plan(1000), 1), (plan(100), 1)] };
+
+        // The second tier is split from the first one's change, and 500 and 3000 are left over
+        let (_, roots) = mix.forecast_roots(&[500, 10_000, 3000], 4);
+        assert_eq!(roots, vec![1]);
+    }
+
+    #[test]
+    fn max_count_fits_the_balance() {
+        let single = DenominationPlan { tiers: vec![(plan(1000), 1)] };
+        assert_eq!(single.max_count(&[5000]), 4);
+        assert_eq!(single.max_count(&[500]), 0);
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+const LATENCY_BUCKETS: usize = 32;
+
+/// Lock-free latency histogram with power-of-two microsecond buckets
+pub struct LatencyStats {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+    sum_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl Default for LatencyStats {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum_us: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LatencyStats {
+    pub fn record(&self, latency: Duration) {
+        let us = latency.as_micros() as u64;
+        let bucket = (u64::BITS - us.leading_zeros()) as usize;
+        self.buckets[bucket.min(LATENCY_BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    /// Reads and resets the histogram
+    fn take(&self) -> LatencySnapshot {
+        LatencySnapshot {
+            buckets: std::array::from_fn(|i| self.buckets[i].swap(0, Ordering::Relaxed)),
+            sum_us: self.sum_us.swap(0, Ordering::Relaxed),
+            max_us: self.max_us.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Latency histogram for one stats interval, mergeable across workers
+#[derive(Debug, Clone, Copy)]
+pub struct LatencySnapshot {
+    buckets: [u64; LATENCY_BUCKETS],
+    sum_us: u64,
+    max_us: u64,
+}
+
+impl Default for LatencySnapshot {
+    fn default() -> Self {
+        Self {
+            buckets: [0; LATENCY_BUCKETS],
+            sum_us: 0,
+            max_us: 0,
+        }
+    }
+}
+
+impl LatencySnapshot {
+    fn merge(&mut self, other: &LatencySnapshot) {
+        for (total, count) in self.buckets.iter_mut().zip(other.buckets) {
+            *total += count;
+        }
+        self.sum_us += other.sum_us;
+        self.max_us = self.max_us.max(other.max_us);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    pub fn mean_ms(&self) -> f64 {
+        match self.count() {
+            0 => 0.0,
+            count => self.sum_us as f64 / count as f64 / 1000.0,
+        }
+    }
+
+    pub fn max_ms(&self) -> f64 {
+        self.max_us as f64 / 1000.0
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile, capped at the observed max
+    pub fn percentile_ms(&self, q: f64) -> f64 {
+        let count = self.count();
+        if count == 0 {
+            return 0.0;
+        }
+
+        let rank = ((count as f64) * q).ceil() as u64;
+        let mut seen = 0u64;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket;
+            if seen >= rank {
+                let upper_us = if i == 0 { 0 } else { 1u64 << i };
+                return upper_us.min(self.max_us) as f64 / 1000.0;
+            }
+        }
+        self.max_ms()
+    }
+}
+
+/// Counters and gauges published by a single spam worker
+#[derive(Default)]
+pub struct WorkerStats {
+    pub sent: AtomicU64,
+    pub failed: AtomicU64,
+    /// Failed submissions whose input the node had removed in a reorg or competing spend
+    pub invalidated: AtomicU64,
+    pub starved_ticks: AtomicU64,
+    pub inflight: AtomicUsize,
+    pub buffered: AtomicUsize,
+    /// Time from submit call to node response
+    pub submit_latency: LatencyStats,
+    /// Time between a scheduled send and the moment it actually happened
+    pub schedule_lag: LatencyStats,
+}
+
+/// Totals across all workers at one point in time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StatsSnapshot {
+    pub sent: u64,
+    pub failed: u64,
+    pub invalidated: u64,
+    pub starved_ticks: u64,
+    pub inflight: usize,
+    pub buffered: usize,
+    pub submit_latency: LatencySnapshot,
+    pub schedule_lag: LatencySnapshot,
+}
+
+pub struct SpamStats {
+    workers: Vec<WorkerStats>,
+    // Totals carried over from a resumed run
+    base_sent: u64,
+    base_failed: u64,
+}
+
+impl SpamStats {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: (0..workers).map(|_| WorkerStats::default()).collect(),
+            base_sent: 0,
+            base_failed: 0,
+        }
+    }
+
+    /// Starts the totals from a previous run's counters
+    pub fn resume_from(mut self, sent: u64, failed: u64) -> Self {
+        self.base_sent = sent;
+        self.base_failed = failed;
+        self
+    }
+
+    pub fn worker(&self, id: usize) -> &WorkerStats {
+        &self.workers[id]
+    }
+
+    /// Sums all workers. Per-interval values (starved ticks, latencies) are reset on read.
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let mut snapshot = StatsSnapshot {
+            sent: self.base_sent,
+            failed: self.base_failed,
+            ..Default::default()
+        };
+        for worker in &self.workers {
+            snapshot.sent += worker.sent.load(Ordering::Relaxed);
+            snapshot.failed += worker.failed.load(Ordering::Relaxed);
+            snapshot.invalidated += worker.invalidated.load(Ordering::Relaxed);
+            snapshot.starved_ticks += worker.starved_ticks.swap(0, Ordering::Relaxed);
+            snapshot.inflight += worker.inflight.load(Ordering::Relaxed);
+            snapshot.buffered += worker.buffered.load(Ordering::Relaxed);
+            snapshot.submit_latency.merge(&worker.submit_latency.take());
+            snapshot.schedule_lag.merge(&worker.schedule_lag.take());
+        }
+        snapshot
+    }
+
+    /// Run totals of sent and failed transactions, without resetting anything
+    pub fn totals(&self) -> (u64, u64) {
+        self.workers.iter().fold((self.base_sent, self.base_failed), |(sent, failed), w| {
+            (sent + w.sent.load(Ordering::Relaxed), failed + w.failed.load(Ordering::Relaxed))
+        })
+    }
+
+    /// Total transactions sent by each worker, for spotting imbalanced shards
+    pub fn sent_per_worker(&self) -> Vec<u64> {
+        self.workers.iter().map(|w| w.sent.load(Ordering::Relaxed)).collect()
+    }
+}
@@ -0,0 +1,66 @@
+use crate::config::Config;
+use crate::error::Result;
+use crate::network::{connect_client, rpc_url};
+use kaspa_rpc_core::{api::rpc::RpcApi, model::GetServerInfoRequest};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::debug;
+
+/// Latest node state, published by the telemetry task
+#[derive(Debug, Clone, Default)]
+pub struct NodeTelemetry {
+    pub mempool_size: u64,
+    pub virtual_daa_score: u64,
+    pub is_synced: bool,
+    pub updated_at: Option<Instant>,
+}
+
+impl NodeTelemetry {
+    /// Seconds since the last successful poll, or `None` before the first one
+    pub fn age_secs(&self) -> Option<u64> {
+        self.updated_at.map(|t| t.elapsed().as_secs())
+    }
+}
+
+/// Polls node info on a dedicated connection and publishes each snapshot through a
+/// watch channel, so slow node responses never delay the submission loops.
+pub async fn spawn_telemetry(config: &Config) -> Result<(watch::Receiver<NodeTelemetry>, JoinHandle<()>)> {
+    let client = connect_client(&rpc_url(config)).await?;
+    let (tx, rx) = watch::channel(NodeTelemetry::default());
+    let poll_every = Duration::from_millis(config.advanced.telemetry_interval_ms.max(100));
+
+    let handle = tokio::spawn(async move {
+        let mut ticker = interval(poll_every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            ticker.tick().await;
+
+            let (info, server_info) = tokio::join!(
+                client.get_info(),
+                client.get_server_info_call(None, GetServerInfoRequest {}),
+            );
+
+            match (info, server_info) {
+                (Ok(info), Ok(server_info)) => {
+                    let snapshot = NodeTelemetry {
+                        mempool_size: info.mempool_size,
+                        virtual_daa_score: server_info.virtual_daa_score,
+                        is_synced: info.is_synced && server_info.is_synced,
+                        updated_at: Some(Instant::now()),
+                    };
+                    if tx.send(snapshot).is_err() {
+                        break;
+                    }
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    debug!("Telemetry poll failed: {}", e);
+                }
+            }
+        }
+    });
+
+    Ok((rx, handle))
+}
@@ -0,0 +1,29 @@
+//! Fixtures shared by the unit tests
+
+use crate::config::Config;
+use crate::wallet::Wallet;
+use kaspa_consensus_core::tx::{ScriptPublicKey, TransactionId, TransactionOutpoint, UtxoEntry as CoreUtxoEntry};
+use std::path::PathBuf;
+
+pub fn txid(n: u8) -> TransactionId {
+    TransactionId::from_bytes([n; 32])
+}
+
+pub fn outpoint(n: u8) -> TransactionOutpoint {
+    TransactionOutpoint::new(txid(n), 0)
+}
+
+/// A UTXO of `amount` sompi at `outpoint(n)`, locked to an anyone-can-spend script
+pub fn utxo(n: u8, amount: u64) -> (TransactionOutpoint, CoreUtxoEntry) {
+    (outpoint(n), CoreUtxoEntry::new(amount, ScriptPublicKey::from_vec(0, vec![0x51]), 0, false))
+}
+
+/// A wallet holding a single fixed key, on the default network
+pub fn wallet() -> Wallet {
+    Wallet::from_secret(&"01".repeat(32), &Config::default()).unwrap()
+}
+
+/// A file in the temporary directory, unique to this test process
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tx-gen-{}-{}.json", std::process::id(), name))
+}
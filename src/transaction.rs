@@ -6,39 +6,113 @@ use kaspa_consensus_core::{
     sign::sign,
     subnets::SUBNETWORK_ID_NATIVE,
     tx::{
-        MutableTransaction, Transaction, TransactionInput, TransactionOutput,
+        MutableTransaction, ScriptPublicKey, Transaction, TransactionInput, TransactionOutput,
         TransactionOutpoint, UtxoEntry as CoreUtxoEntry,
     },
 };
 use kaspa_txscript::pay_to_address_script;
 use secp256k1::Keypair;
+use std::iter;
 use tracing::trace;
 
-pub fn estimated_mass(_num_inputs: usize, _num_outputs: u64) -> u64 {
-    // TODO: Implement more accurate mass estimation
-    1700
+/// Sompi in one KAS, for turning amounts into readable figures
+pub const SOMPI_PER_KAS: f64 = 100_000_000.0;
+
+/// Marker at the start of every tagged payload: `TXG1 | run_id (u64 LE) | seq (u64 LE)`
+pub const PAYLOAD_MAGIC: &[u8; 4] = b"TXG1";
+
+/// Length of a tagged payload
+pub const RUN_PAYLOAD_LEN: usize = PAYLOAD_MAGIC.len() + 16;
+
+/// Builds the payload that attributes a transaction to a run and submission sequence number
+pub fn encode_run_payload(run_id: u64, seq: u64) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(RUN_PAYLOAD_LEN);
+    payload.extend_from_slice(PAYLOAD_MAGIC);
+    payload.extend_from_slice(&run_id.to_le_bytes());
+    payload.extend_from_slice(&seq.to_le_bytes());
+    payload
+}
+
+// Consensus mass weights of a serialized byte, a script public key byte and a signature operation
+const MASS_PER_TX_BYTE: u64 = 1;
+const MASS_PER_SCRIPT_PUB_KEY_BYTE: u64 = 10;
+const MASS_PER_SIG_OP: u64 = 1000;
+
+/// Wallet keys lock outputs with a push of the 32-byte public key and OP_CHECKSIG
+const PAY_TO_PUBKEY_SCRIPT_LEN: usize = 34;
+
+/// Wallet keys unlock inputs with a push of the 64-byte Schnorr signature and its sighash type
+const SIGNATURE_SCRIPT_LEN: usize = 66;
+
+/// Compute mass of a signed transaction, from its serialized size, output scripts and
+/// signature operations, as the node charges it
+pub fn transaction_mass(tx: &Transaction) -> u64 {
+    compute_mass(
+        tx.payload.len(),
+        tx.inputs.iter().map(|input| (input.signature_script.len(), input.sig_op_count)),
+        tx.outputs.iter().map(|output| output.script_public_key.script().len()),
+    )
+}
+
+/// Compute mass a transaction spending `num_inputs` wallet UTXOs into `num_outputs` wallet
+/// outputs will have once signed, for pricing it before it is built
+pub fn estimated_mass(num_inputs: usize, num_outputs: usize, payload_len: usize) -> u64 {
+    compute_mass(
+        payload_len,
+        iter::repeat((SIGNATURE_SCRIPT_LEN, 1)).take(num_inputs),
+        iter::repeat(PAY_TO_PUBKEY_SCRIPT_LEN).take(num_outputs),
+    )
+}
+
+/// Mass of a transaction given its payload length, the signature script length and signature
+/// operations of each input, and the script length of each output
+fn compute_mass(
+    payload_len: usize,
+    inputs: impl Iterator<Item = (usize, u8)>,
+    output_scripts: impl Iterator<Item = usize>,
+) -> u64 {
+    // Version, input and output counts, lock time, subnetwork, gas, payload hash and length
+    let mut size = 2 + 8 + 8 + 8 + 20 + 8 + 32 + 8 + payload_len as u64;
+    let mut script_bytes = 0;
+    let mut sig_ops = 0;
+
+    for (signature_script, sig_op_count) in inputs {
+        // Outpoint, signature script length and sequence
+        size += 32 + 4 + 8 + signature_script as u64 + 8;
+        sig_ops += sig_op_count as u64;
+    }
+    for script in output_scripts {
+        // Value, script version and script length
+        let script = 2 + script as u64;
+        size += 8 + 8 + script;
+        script_bytes += script;
+    }
+
+    size * MASS_PER_TX_BYTE + script_bytes * MASS_PER_SCRIPT_PUB_KEY_BYTE + sig_ops * MASS_PER_SIG_OP
 }
 
-pub fn calculate_fee(config: &Config, num_inputs: usize, num_outputs: u64, is_splitting: bool) -> u64 {
+/// Fee for a transaction of the given shape, at the splitting or the spam fee rate
+pub fn calculate_fee(
+    config: &Config,
+    num_inputs: usize,
+    num_outputs: usize,
+    payload_len: usize,
+    is_splitting: bool,
+) -> u64 {
     let fee_rate = if is_splitting {
         config.fees.splitting_fee_rate
     } else {
         config.fees.base_fee_rate
     };
-    fee_rate * estimated_mass(num_inputs, num_outputs)
+    fee_rate * estimated_mass(num_inputs, num_outputs, payload_len)
 }
 
-pub fn create_splitting_transaction(
+/// Spends one UTXO into outputs of the given values, each paying to its own script
+pub fn create_fanout_transaction(
     keypair: &Keypair,
     utxo: &(TransactionOutpoint, CoreUtxoEntry),
-    amount_per_output: u64,
-    num_target_outputs: usize,
-    change_value: u64,
-    address: &Address,
-    min_change: u64,
+    outputs: &[(u64, ScriptPublicKey)],
 ) -> Result<Transaction> {
-    let script_public_key = pay_to_address_script(address);
-
     let inputs = vec![TransactionInput {
         previous_outpoint: utxo.0,
         signature_script: vec![],
@@ -46,28 +120,18 @@ pub fn create_splitting_transaction(
         sig_op_count: 1,
     }];
 
-    let mut outputs = Vec::with_capacity(num_target_outputs + 1);
-
-    // Add target outputs
-    for _ in 0..num_target_outputs {
-        outputs.push(TransactionOutput {
-            value: amount_per_output,
-            script_public_key: script_public_key.clone(),
-        });
-    }
-
-    // Add change output if significant
-    if change_value >= min_change {
-        outputs.push(TransactionOutput {
-            value: change_value,
+    let tx_outputs = outputs
+        .iter()
+        .map(|(value, script_public_key)| TransactionOutput {
+            value: *value,
             script_public_key: script_public_key.clone(),
-        });
-    }
+        })
+        .collect();
 
     let unsigned_tx = Transaction::new(
         TX_VERSION,
         inputs,
-        outputs,
+        tx_outputs,
         0,
         SUBNETWORK_ID_NATIVE,
         0,
@@ -79,12 +143,50 @@ pub fn create_splitting_transaction(
         keypair.clone(),
     );
 
-    trace!(
-        "Created splitting transaction: {} outputs, change: {} sompi",
-        num_target_outputs,
-        if change_value >= min_change { change_value } else { 0 }
+    trace!("Created fan-out transaction with {} outputs", outputs.len());
+
+    Ok(signed_tx.tx)
+}
+
+/// Spends several UTXOs locked to the same key into outputs of the given values and scripts
+pub fn create_funding_transaction(
+    keypair: &Keypair,
+    utxos: &[(TransactionOutpoint, CoreUtxoEntry)],
+    outputs: &[(u64, ScriptPublicKey)],
+) -> Result<Transaction> {
+    let inputs = utxos
+        .iter()
+        .map(|(outpoint, _)| TransactionInput {
+            previous_outpoint: *outpoint,
+            signature_script: vec![],
+            sequence: 0,
+            sig_op_count: 1,
+        })
+        .collect();
+
+    let tx_outputs = outputs
+        .iter()
+        .map(|(value, script_public_key)| TransactionOutput {
+            value: *value,
+            script_public_key: script_public_key.clone(),
+        })
+        .collect();
+
+    let unsigned_tx = Transaction::new(
+        TX_VERSION,
+        inputs,
+        tx_outputs,
+        0,
+        SUBNETWORK_ID_NATIVE,
+        0,
+        vec![],
     );
 
+    let entries = utxos.iter().map(|(_, entry)| entry.clone()).collect();
+    let signed_tx = sign(MutableTransaction::with_entries(unsigned_tx, entries), keypair.clone());
+
+    trace!("Created funding transaction with {} inputs and {} outputs", utxos.len(), outputs.len());
+
     Ok(signed_tx.tx)
 }
 
@@ -94,6 +196,7 @@ pub fn create_spam_transaction(
     input_entry: CoreUtxoEntry,
     output_amount: u64,
     address: &Address,
+    payload: Vec<u8>,
 ) -> Result<Transaction> {
     let script_public_key = pay_to_address_script(address);
 
@@ -116,7 +219,7 @@ pub fn create_spam_transaction(
         0,
         SUBNETWORK_ID_NATIVE,
         0,
-        vec![],
+        payload,
     );
 
     let signed_tx = sign(
@@ -125,4 +228,44 @@ pub fn create_spam_transaction(
     );
 
     Ok(signed_tx.tx)
-}
\ No newline at end of file
+}
+
+/// The output of a spam transaction as a UTXO we can spend next. Its DAA score
+/// is unknown until the node reports it.
+pub fn spam_output(tx: &Transaction) -> (TransactionOutpoint, CoreUtxoEntry) {
+    let output = &tx.outputs[0];
+    (
+        TransactionOutpoint::new(tx.id(), 0),
+        CoreUtxoEntry::new(output.value, output.script_public_key.clone(), 0, false),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{utxo, wallet};
+
+    #[test]
+    fn run_payload_is_magic_then_run_id_then_sequence() {
+        let payload = encode_run_payload(0x0102_0304_0506_0708, 9);
+        assert_eq!(payload.len(), RUN_PAYLOAD_LEN);
+        assert_eq!(&payload[..4], PAYLOAD_MAGIC);
+        assert_eq!(payload[4..12], [8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(payload[12..], 9u64.to_le_bytes());
+    }
+
+    #[test]
+    fn estimated_mass_matches_signed_transactions() {
+        let wallet = wallet();
+        let key = wallet.primary();
+
+        let (outpoint, entry) = utxo(1, 100_000_000);
+        let payload = encode_run_payload(7, 42);
+        let tx = create_spam_transaction(&key.keypair, outpoint, entry, 99_000_000, &key.address, payload).unwrap();
+        assert_eq!(transaction_mass(&tx), estimated_mass(1, 1, RUN_PAYLOAD_LEN));
+
+        let outputs = vec![(1_000_000, key.script_public_key.clone()); 3];
+        let tx = create_fanout_transaction(&key.keypair, &utxo(2, 100_000_000), &outputs).unwrap();
+        assert_eq!(transaction_mass(&tx), estimated_mass(1, 3, 0));
+    }
+}
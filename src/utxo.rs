@@ -1,24 +1,64 @@
 use crate::config::Config;
 use crate::error::Result;
+use crate::notifications::UtxoSubscription;
+use crate::selection::{PoolStats, ReadyQueue, Selection};
+use crate::telemetry::NodeTelemetry;
+use crate::transaction::SOMPI_PER_KAS;
 use kaspa_addresses::Address;
-use kaspa_consensus_core::tx::{TransactionOutpoint, UtxoEntry as CoreUtxoEntry};
+use kaspa_consensus_core::tx::{ScriptPublicKey, TransactionId, TransactionOutpoint, UtxoEntry as CoreUtxoEntry};
 use kaspa_grpc_client::GrpcClient;
 use kaspa_rpc_core::{
     api::rpc::RpcApi,
-    model::{GetServerInfoRequest, GetUtxosByAddressesRequest, RpcUtxoEntry},
+    model::{
+        GetMempoolEntriesByAddressesRequest, GetServerInfoRequest, GetUtxosByAddressesRequest,
+        RpcUtxosByAddressesEntry,
+    },
+    UtxosChangedNotification,
 };
-use std::collections::{HashMap, HashSet};
-use std::time::Instant;
-use tracing::{debug, info};
+use kaspa_txscript::pay_to_address_script;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
 
-pub async fn fetch_spendable_utxos(
+/// Our UTXOs as seen by the node, split by whether they can be spent yet
+#[derive(Default)]
+pub struct UtxoSet {
+    pub spendable: Vec<(TransactionOutpoint, CoreUtxoEntry)>,
+    /// Not deep enough yet, with the DAA score at which each becomes spendable
+    pub immature: Vec<(TransactionOutpoint, CoreUtxoEntry, u64)>,
+    pub malformed: MalformedEntries,
+}
+
+/// Entries of a `get_utxos_by_addresses` response that did not check out
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MalformedEntries {
+    /// Locked by a script none of our addresses pay to; skipped
+    pub unknown_script: usize,
+    /// Reported under a different address than the one its script pays to; skipped
+    pub wrong_address: usize,
+    /// Reported more than once; later copies skipped
+    pub duplicate: usize,
+    /// Reported without an address but owned by its script; kept
+    pub missing_address: usize,
+}
+
+impl MalformedEntries {
+    pub fn total(&self) -> usize {
+        self.unknown_script + self.wrong_address + self.duplicate + self.missing_address
+    }
+}
+
+pub async fn fetch_utxo_set(
     client: &GrpcClient,
-    address: Address,
+    addresses: &[Address],
     config: &Config,
-) -> Result<Vec<(TransactionOutpoint, CoreUtxoEntry)>> {
+) -> Result<UtxoSet> {
     let resp = client
         .get_utxos_by_addresses_call(None, GetUtxosByAddressesRequest {
-            addresses: vec![address.clone()],
+            addresses: addresses.to_vec(),
         })
         .await?;
 
@@ -26,146 +66,1787 @@ pub async fn fetch_spendable_utxos(
         .get_server_info_call(None, GetServerInfoRequest {})
         .await?;
 
-    let virtual_daa_score = server_info.virtual_daa_score;
+    let set = sort_utxo_entries(resp.entries, addresses, server_info.virtual_daa_score, config);
 
-    let mut utxos = Vec::with_capacity(resp.entries.len());
+    if set.malformed.total() > 0 {
+        warn!(
+            "UTXO response had {} malformed entries: {} unknown script, {} wrong address, {} duplicate, {} missing address",
+            set.malformed.total(),
+            set.malformed.unknown_script,
+            set.malformed.wrong_address,
+            set.malformed.duplicate,
+            set.malformed.missing_address
+        );
+    }
 
-    for entry in resp.entries {
-        if is_utxo_spendable(&entry.utxo_entry, virtual_daa_score, config) {
-            assert!(entry.address.is_some());
-            assert_eq!(*entry.address.as_ref().unwrap(), address);
+    debug!(
+        "Fetched {} spendable UTXOs (total: {} sompi), {} immature",
+        set.spendable.len(),
+        set.spendable.iter().map(|(_, e)| e.amount).sum::<u64>(),
+        set.immature.len()
+    );
 
-            utxos.push((
-                TransactionOutpoint::from(entry.outpoint),
-                CoreUtxoEntry::from(entry.utxo_entry),
-            ));
+    Ok(set)
+}
+
+/// Splits the entries of a `get_utxos_by_addresses` response into UTXOs spendable at
+/// `virtual_daa_score` and immature ones, skipping and counting malformed entries
+fn sort_utxo_entries(
+    entries: Vec<RpcUtxosByAddressesEntry>,
+    addresses: &[Address],
+    virtual_daa_score: u64,
+    config: &Config,
+) -> UtxoSet {
+    // Each entry must be locked to one of our addresses, since that is what we can sign for
+    let owners: HashMap<ScriptPublicKey, &Address> =
+        addresses.iter().map(|address| (pay_to_address_script(address), address)).collect();
+
+    let mut set = UtxoSet {
+        spendable: Vec::with_capacity(entries.len()),
+        ..Default::default()
+    };
+    let mut seen = HashSet::with_capacity(entries.len());
+
+    for entry in entries {
+        let outpoint = TransactionOutpoint::from(entry.outpoint);
+        let utxo_entry = CoreUtxoEntry::from(entry.utxo_entry);
+
+        let Some(&owner) = owners.get(&utxo_entry.script_public_key) else {
+            debug!("Skipping UTXO {} locked to a script we do not own", outpoint);
+            set.malformed.unknown_script += 1;
+            continue;
+        };
+        match &entry.address {
+            Some(address) if address != owner => {
+                debug!("Skipping UTXO {} reported for {} but paying {}", outpoint, address, owner);
+                set.malformed.wrong_address += 1;
+                continue;
+            }
+            Some(_) => {}
+            None => set.malformed.missing_address += 1,
+        }
+        if !seen.insert(outpoint) {
+            set.malformed.duplicate += 1;
+            continue;
+        }
+
+        let ready_at = spendable_at(&utxo_entry, config);
+        if ready_at <= virtual_daa_score {
+            set.spendable.push((outpoint, utxo_entry));
+        } else {
+            set.immature.push((outpoint, utxo_entry, ready_at));
         }
     }
 
     // Sort by amount (largest first) for better fee handling
-    utxos.sort_by(|a, b| b.1.amount.cmp(&a.1.amount));
+    set.spendable.sort_by(|a, b| b.1.amount.cmp(&a.1.amount));
 
-    debug!(
-        "Fetched {} spendable UTXOs (total: {} sompi)",
-        utxos.len(),
-        utxos.iter().map(|(_, e)| e.amount).sum::<u64>()
-    );
+    set
+}
+
+pub async fn fetch_spendable_utxos(
+    client: &GrpcClient,
+    addresses: &[Address],
+    config: &Config,
+) -> Result<Vec<(TransactionOutpoint, CoreUtxoEntry)>> {
+    Ok(fetch_utxo_set(client, addresses, config).await?.spendable)
+}
+
+/// Outpoints spent by transactions in the node's mempool, including orphans,
+/// mapped to the spending transaction where the node reports its id
+pub async fn fetch_mempool_spends(
+    client: &GrpcClient,
+    addresses: Vec<Address>,
+) -> Result<HashMap<TransactionOutpoint, Option<TransactionId>>> {
+    let resp = client
+        .get_mempool_entries_by_addresses_call(None, GetMempoolEntriesByAddressesRequest {
+            addresses,
+            include_orphan_pool: true,
+            filter_transaction_pool: false,
+        })
+        .await?;
 
-    Ok(utxos)
+    let mut spends = HashMap::new();
+    for entry in resp.entries.iter().flat_map(|e| &e.sending) {
+        let txid = entry.transaction.verbose_data.as_ref().map(|v| v.transaction_id);
+        for input in &entry.transaction.inputs {
+            spends.insert(TransactionOutpoint::from(input.previous_outpoint.clone()), txid);
+        }
+    }
+
+    Ok(spends)
 }
 
-fn is_utxo_spendable(entry: &RpcUtxoEntry, virtual_daa_score: u64, config: &Config) -> bool {
+/// DAA score at which a UTXO has enough confirmations to be spent
+pub fn spendable_at(entry: &CoreUtxoEntry, config: &Config) -> u64 {
     let needed_confirmations = if !entry.is_coinbase {
         config.advanced.confirmation_depth
     } else {
         config.advanced.coinbase_maturity
     };
 
-    entry.block_daa_score + needed_confirmations <= virtual_daa_score
+    entry.block_daa_score + needed_confirmations
 }
 
+/// UTXOs seen by the node but not yet deep enough to spend, ordered by the DAA
+/// score at which they mature
+#[derive(Default)]
+pub struct MaturityQueue {
+    by_score: BTreeMap<u64, Vec<(TransactionOutpoint, CoreUtxoEntry)>>,
+    pending: HashSet<TransactionOutpoint>,
+    // Coinbase outputs handed to the pool since startup, kept across refreshes
+    matured_coinbase: usize,
+    matured_coinbase_amount: u64,
+}
+
+impl MaturityQueue {
+    pub fn push(&mut self, outpoint: TransactionOutpoint, entry: CoreUtxoEntry, ready_at: u64) {
+        if self.pending.insert(outpoint) {
+            self.by_score.entry(ready_at).or_default().push((outpoint, entry));
+        }
+    }
+
+    pub fn remove(&mut self, outpoint: &TransactionOutpoint) -> bool {
+        self.pending.remove(outpoint)
+    }
+
+    /// Takes every UTXO that is spendable at `virtual_daa_score`
+    pub fn pop_matured(&mut self, virtual_daa_score: u64) -> Vec<(TransactionOutpoint, CoreUtxoEntry)> {
+        let still_maturing = self.by_score.split_off(&(virtual_daa_score + 1));
+        let matured = std::mem::replace(&mut self.by_score, still_maturing);
+
+        let matured: Vec<_> = matured
+            .into_values()
+            .flatten()
+            .filter(|(op, _)| self.pending.remove(op))
+            .collect();
+
+        for (_, entry) in matured.iter().filter(|(_, entry)| entry.is_coinbase) {
+            self.matured_coinbase += 1;
+            self.matured_coinbase_amount += entry.amount;
+        }
+        matured
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Drops every queued UTXO, keeping the matured totals
+    pub fn clear(&mut self) {
+        self.by_score.clear();
+        self.pending.clear();
+    }
+
+    /// Summarises what is maturing at `virtual_daa_score`, with coinbase outputs
+    /// bucketed by quarters of `coinbase_maturity`
+    pub fn report(&self, virtual_daa_score: u64, coinbase_maturity: u64) -> MaturityReport {
+        let quarter = (coinbase_maturity / 4).max(1);
+        let mut report = MaturityReport {
+            matured_coinbase: self.matured_coinbase,
+            matured_coinbase_amount: self.matured_coinbase_amount,
+            ..Default::default()
+        };
+
+        for (_, entry, ready_at) in self.entries() {
+            let remaining = ready_at.saturating_sub(virtual_daa_score);
+            report.next_in = Some(report.next_in.map_or(remaining, |next| next.min(remaining)));
+
+            if entry.is_coinbase {
+                report.coinbase += 1;
+                report.coinbase_amount += entry.amount;
+                report.coinbase_by_quarter[((remaining / quarter) as usize).min(3)] += 1;
+            } else {
+                report.regular += 1;
+                report.regular_amount += entry.amount;
+            }
+        }
+        report
+    }
+
+    /// Every queued UTXO with the DAA score at which it matures
+    pub fn entries(&self) -> impl Iterator<Item = (&TransactionOutpoint, &CoreUtxoEntry, u64)> + '_ {
+        // A UTXO removed and queued again sits in `by_score` twice; keep the first
+        let mut seen = HashSet::new();
+        self.by_score
+            .iter()
+            .flat_map(|(&ready_at, utxos)| utxos.iter().map(move |(op, entry)| (op, entry, ready_at)))
+            .filter(move |(op, _, _)| self.pending.contains(*op) && seen.insert(**op))
+    }
+}
+
+/// The maturing pipeline: what the queue holds and what it has released so far
+#[derive(Debug, Default, Clone)]
+pub struct MaturityReport {
+    pub coinbase: usize,
+    pub coinbase_amount: u64,
+    /// Queued coinbase outputs by how soon they mature, in quarters of the maturity
+    pub coinbase_by_quarter: [usize; 4],
+    pub regular: usize,
+    pub regular_amount: u64,
+    /// DAA scores until the next UTXO matures
+    pub next_in: Option<u64>,
+    pub matured_coinbase: usize,
+    pub matured_coinbase_amount: u64,
+}
+
+impl MaturityReport {
+    pub fn is_empty(&self) -> bool {
+        self.coinbase == 0 && self.regular == 0 && self.matured_coinbase == 0
+    }
+}
+
+impl fmt::Display for MaturityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} coinbase ({:.2} KAS, by quarter {:?}) | {} regular ({:.2} KAS) | next in: ",
+            self.coinbase,
+            self.coinbase_amount as f64 / SOMPI_PER_KAS,
+            self.coinbase_by_quarter,
+            self.regular,
+            self.regular_amount as f64 / SOMPI_PER_KAS,
+        )?;
+        match self.next_in {
+            Some(daa) => write!(f, "{} DAA", daa)?,
+            None => write!(f, "-")?,
+        }
+        write!(
+            f,
+            " | matured coinbase: {} ({:.2} KAS)",
+            self.matured_coinbase,
+            self.matured_coinbase_amount as f64 / SOMPI_PER_KAS
+        )
+    }
+}
+
+/// Maturity queue shared between the refresher and anything reporting on it
+pub type SharedMaturity = Arc<Mutex<MaturityQueue>>;
+
+pub fn lock_maturity(queue: &SharedMaturity) -> MutexGuard<'_, MaturityQueue> {
+    queue.lock().expect("maturity queue lock poisoned")
+}
+
+/// Lifecycle of a UTXO held by the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtxoState {
+    /// Spendable and waiting to be handed out
+    Available,
+    /// Handed out for signing, buffered or in flight
+    Reserved,
+    /// Spending transaction accepted by submit
+    Submitted,
+}
+
+const STATE_COUNT: usize = 3;
+
+/// Where a pending spend turned out to be when checked against the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingFate {
+    /// Spent by a transaction in the mempool
+    InMempool,
+    /// Gone from the UTXO set, so the spend was accepted into the chain
+    Accepted,
+    /// Still unspent with nothing spending it: the transaction never made it
+    Lost,
+}
+
+/// How a UTXO the node stopped reporting left the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// Spent by a transaction we handed to the node
+    Spent,
+    /// Gone before we sent a spend, by a reorg or a competing spend; holds the state
+    /// it was in
+    Invalidated(UtxoState),
+}
+
+// Stale queue entries tolerated before a queue is compacted
+const QUEUE_SLACK: usize = 1024;
+
+// How long a spent local output is remembered, so a late report of it from the
+// node is recognised instead of handed out again
+const LOCAL_SPENT_RETENTION: Duration = Duration::from_secs(120);
+
+// How long an invalidated outpoint is remembered, so a failed submission that spent
+// it can be flagged
+const INVALIDATED_RETENTION: Duration = Duration::from_secs(300);
+
+struct Slot {
+    outpoint: TransactionOutpoint,
+    entry: CoreUtxoEntry,
+    state: UtxoState,
+    since: Instant,
+    // Output of one of our own transactions that the node has not reported yet
+    local: bool,
+    // Transaction that spent this UTXO, once submitted
+    spender: Option<TransactionId>,
+    // A spend of it was handed to the node, so it may be gone even while reserved
+    attempted: bool,
+}
+
+/// Slab-backed UTXO pool. Lookups and state changes are O(1); handing a UTXO out or
+/// back goes through the ready queue, which is O(1) for the random strategy and
+/// O(log n) for the ordered ones. Refresh reconciliation, `take_large` and pool
+/// statistics scan the slab. Batches are copied into caller-owned buffers so the hot
+/// path does not allocate.
 pub struct UtxoManager {
-    pub available: Vec<(TransactionOutpoint, CoreUtxoEntry)>,
-    pub pending: HashMap<TransactionOutpoint, Instant>,
-    pub spent: HashSet<TransactionOutpoint>,
+    slots: Vec<Option<Slot>>,
+    vacant: Vec<u32>,
+    index: HashMap<TransactionOutpoint, u32>,
+    // Hand-out order under the selection strategy; may hold stale slot ids, which
+    // are skipped when popped
+    ready: ReadyQueue,
+    // Reservation order with the timestamp each reservation was made
+    reservations: VecDeque<(u32, Instant)>,
+    // Reserved and submitted slots, so the journal and reconciliation need not scan the slab
+    pending: HashSet<u32>,
+    // Local outputs already spent, in the order they were submitted
+    spent_local: VecDeque<(u32, Instant)>,
+    // Outputs of our accepted transactions, waiting for the node to report them
+    expected: HashMap<TransactionOutpoint, (CoreUtxoEntry, Instant)>,
+    expected_order: VecDeque<(TransactionOutpoint, Instant)>,
+    // Local outputs discarded while reserved, whose spending transactions must not be sent
+    cancelled: HashSet<TransactionOutpoint>,
+    counts: [usize; STATE_COUNT],
+    available_balance: u64,
+    // Submitted UTXOs evicted once the node stopped reporting them
+    confirmed_spent: u64,
+    // UTXOs the node removed before we spent them, by a reorg or a competing spend
+    invalidated: HashSet<TransactionOutpoint>,
+    invalidated_order: VecDeque<(TransactionOutpoint, Instant)>,
+    invalidations: u64,
     last_refresh: Instant,
-    index: usize,
 }
 
 impl UtxoManager {
-    pub fn new(utxos: Vec<(TransactionOutpoint, CoreUtxoEntry)>) -> Self {
+    pub fn new(utxos: Vec<(TransactionOutpoint, CoreUtxoEntry)>, selection: Selection) -> Self {
         info!("Initialized UTXO manager with {} UTXOs", utxos.len());
-        Self {
-            available: utxos,
-            pending: HashMap::new(),
-            spent: HashSet::new(),
+        Self::with_utxos(utxos, selection)
+    }
+
+    fn with_utxos(utxos: Vec<(TransactionOutpoint, CoreUtxoEntry)>, selection: Selection) -> Self {
+        let mut manager = Self {
+            slots: Vec::with_capacity(utxos.len()),
+            vacant: Vec::new(),
+            index: HashMap::with_capacity(utxos.len()),
+            ready: ReadyQueue::new(selection),
+            reservations: VecDeque::new(),
+            pending: HashSet::new(),
+            spent_local: VecDeque::new(),
+            expected: HashMap::new(),
+            expected_order: VecDeque::new(),
+            cancelled: HashSet::new(),
+            counts: [0; STATE_COUNT],
+            available_balance: 0,
+            confirmed_spent: 0,
+            invalidated: HashSet::new(),
+            invalidated_order: VecDeque::new(),
+            invalidations: 0,
             last_refresh: Instant::now(),
-            index: 0,
-        }
+        };
+        manager.adopt(utxos);
+        manager
     }
 
     pub fn needs_refresh(&self, config: &Config) -> bool {
         self.last_refresh.elapsed().as_secs() >= config.utxo.refresh_interval_secs
-            || self.index >= self.available.len().saturating_sub(8)
+            || self.available_count() <= 8
     }
 
-    pub async fn refresh(
-        &mut self,
-        client: &GrpcClient,
-        address: Address,
-        config: &Config,
-    ) -> Result<()> {
-        let mut fresh = fetch_spendable_utxos(client, address, config).await?;
+    /// Adds an available UTXO unless the pool already tracks its outpoint
+    pub fn insert(&mut self, outpoint: TransactionOutpoint, entry: CoreUtxoEntry) -> bool {
+        if self.index.contains_key(&outpoint) {
+            return false;
+        }
+        self.insert_slot(outpoint, entry);
+        true
+    }
 
-        // Exclude already pending or spent UTXOs
-        fresh.retain(|(op, _)| !self.pending.contains_key(op) && !self.spent.contains(op));
+    fn insert_slot(&mut self, outpoint: TransactionOutpoint, entry: CoreUtxoEntry) -> u32 {
+        self.available_balance += entry.amount;
+        self.counts[UtxoState::Available as usize] += 1;
 
-        let old_count = self.available.len();
-        self.available = fresh;
-        self.index = 0;
-        self.last_refresh = Instant::now();
+        let slot = Slot { outpoint, entry, state: UtxoState::Available, since: Instant::now(), local: false, spender: None, attempted: false };
+        let id = match self.vacant.pop() {
+            Some(id) => {
+                self.slots[id as usize] = Some(slot);
+                id
+            }
+            None => {
+                self.slots.push(Some(slot));
+                (self.slots.len() - 1) as u32
+            }
+        };
 
-        info!(
-            "Refreshed UTXOs: {} available (was {}), {} pending, {} spent",
-            self.available.len(),
-            old_count,
-            self.pending.len(),
-            self.spent.len()
-        );
+        self.index.insert(outpoint, id);
+        let slot = self.slots[id as usize].as_ref().expect("inserted slot is occupied");
+        self.ready.push(id, &slot.entry);
+        id
+    }
 
-        Ok(())
+    /// Adds the output of one of our own transactions before the node reports it,
+    /// so it can be spent right away
+    pub fn insert_local(&mut self, outpoint: TransactionOutpoint, entry: CoreUtxoEntry) -> bool {
+        if self.index.contains_key(&outpoint) {
+            return false;
+        }
+        let id = self.insert_slot(outpoint, entry);
+        self.slots[id as usize].as_mut().expect("inserted slot is occupied").local = true;
+        true
+    }
+
+    /// Drops a local output that will never exist because the transaction creating it
+    /// failed. If it is already reserved for a child transaction that has not been sent,
+    /// the outpoint is remembered so the submitter cancels the child: the node would
+    /// accept it as an orphan, and it would wait there for a parent that never comes.
+    /// Children already sent cannot be recalled.
+    pub fn discard_local(&mut self, outpoint: &TransactionOutpoint) {
+        let Some(&id) = self.index.get(outpoint) else {
+            return;
+        };
+        let Some(slot) = self.slots[id as usize].as_ref().filter(|s| s.local) else {
+            return;
+        };
+
+        match slot.state {
+            UtxoState::Available => {
+                self.remove_slot(id);
+            }
+            UtxoState::Reserved if !slot.attempted => {
+                self.remove_slot(id);
+                self.cancelled.insert(*outpoint);
+            }
+            UtxoState::Reserved | UtxoState::Submitted => {}
+        }
+    }
+
+    /// Whether the transaction spending `outpoint` must not be sent because the parent
+    /// creating it failed. Forgets the outpoint once asked.
+    pub fn take_cancelled(&mut self, outpoint: &TransactionOutpoint) -> bool {
+        self.cancelled.remove(outpoint)
     }
 
-    pub fn get_batch(&mut self, count: usize) -> Vec<(TransactionOutpoint, CoreUtxoEntry)> {
-        let available_count = self.available.len().saturating_sub(self.index);
-        let batch_size = count.min(available_count);
+    /// Remembers the output of one of our accepted transactions, so it joins the
+    /// pool as soon as the node reports it instead of after `confirmation_depth`
+    pub fn expect(&mut self, outpoint: TransactionOutpoint, entry: CoreUtxoEntry) {
+        let now = Instant::now();
+        self.expected.insert(outpoint, (entry, now));
+        self.expected_order.push_back((outpoint, now));
+    }
 
-        if batch_size == 0 {
-            return Vec::new();
+    /// Claims an outpoint the node just reported if this pool created it: expected
+    /// outputs become available and local ones become regular UTXOs. Returns false
+    /// if the outpoint is not ours.
+    pub fn claim_reported(&mut self, outpoint: &TransactionOutpoint, entry: &CoreUtxoEntry) -> bool {
+        if let Some(&id) = self.index.get(outpoint) {
+            let slot = self.slots[id as usize].as_mut().expect("indexed slot is occupied");
+            slot.entry = entry.clone();
+            slot.local = false;
+            return true;
         }
 
-        let batch = self.available[self.index..self.index + batch_size].to_vec();
-        self.index += batch_size;
+        if self.expected.remove(outpoint).is_some() {
+            self.insert_slot(*outpoint, entry.clone());
+            return true;
+        }
+
+        false
+    }
+
+    /// Outpoints this pool created that the node has not reported yet
+    pub fn local_outpoints(&self) -> impl Iterator<Item = &TransactionOutpoint> + '_ {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|s| s.local)
+            .map(|s| &s.outpoint)
+            .chain(self.expected.keys())
+    }
 
-        batch
+    /// Appends UTXOs handed over from another shard or a refresh
+    pub fn adopt(&mut self, utxos: Vec<(TransactionOutpoint, CoreUtxoEntry)>) {
+        for (outpoint, entry) in utxos {
+            self.insert(outpoint, entry);
+        }
     }
 
-    pub fn reserve(&mut self, outpoints: &[(TransactionOutpoint, CoreUtxoEntry)]) {
+    /// Reserves up to `count` available UTXOs, copying them into `out`.
+    /// Returns the number of UTXOs reserved.
+    pub fn take_batch(&mut self, count: usize, out: &mut Vec<(TransactionOutpoint, CoreUtxoEntry)>) -> usize {
         let now = Instant::now();
-        for (op, _) in outpoints {
-            self.pending.insert(*op, now);
+        let mut taken = 0;
+
+        while taken < count {
+            let Some(id) = self.ready.pop_front() else {
+                break;
+            };
+            if !self.is_in_state(id, UtxoState::Available) {
+                continue;
+            }
+
+            self.set_state(id, UtxoState::Reserved, now);
+            let slot = self.slots[id as usize].as_ref().expect("ready slot is occupied");
+            out.push((slot.outpoint, slot.entry.clone()));
+            self.reservations.push_back((id, now));
+            taken += 1;
+        }
+
+        taken
+    }
+
+    /// Reserves every available UTXO worth at least `min_amount`, copying them into `out`.
+    /// Scans the whole pool, so it is meant for occasional maintenance, not the hot path.
+    pub fn take_large(&mut self, min_amount: u64, out: &mut Vec<(TransactionOutpoint, CoreUtxoEntry)>) -> usize {
+        let now = Instant::now();
+        let ids: Vec<u32> = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter_map(|(id, slot)| {
+                slot.as_ref()
+                    .filter(|s| s.state == UtxoState::Available && s.entry.amount >= min_amount)
+                    .map(|_| id as u32)
+            })
+            .collect();
+
+        for &id in &ids {
+            self.set_state(id, UtxoState::Reserved, now);
+            let slot = self.slots[id as usize].as_ref().expect("reserved slot is occupied");
+            out.push((slot.outpoint, slot.entry.clone()));
+            self.reservations.push_back((id, now));
+        }
+
+        ids.len()
+    }
+
+    /// Hands a reserved UTXO back out, for reservations that were never spent
+    pub fn unreserve(&mut self, outpoint: &TransactionOutpoint) {
+        if let Some(&id) = self.index.get(outpoint) {
+            if self.is_in_state(id, UtxoState::Reserved) {
+                self.set_state(id, UtxoState::Available, Instant::now());
+                let slot = self.slots[id as usize].as_ref().expect("indexed slot is occupied");
+                self.ready.push(id, &slot.entry);
+            }
+        }
+    }
+
+    /// Removes up to `count` available UTXOs from the end of the hand-out order
+    pub fn take_remaining(&mut self, count: usize, out: &mut Vec<(TransactionOutpoint, CoreUtxoEntry)>) -> usize {
+        let mut taken = 0;
+
+        while taken < count {
+            let Some(id) = self.ready.pop_back() else {
+                break;
+            };
+            if !self.is_in_state(id, UtxoState::Available) {
+                continue;
+            }
+
+            let slot = self.remove_slot(id);
+            out.push((slot.outpoint, slot.entry));
+            taken += 1;
+        }
+
+        taken
+    }
+
+    /// Records that a transaction spending this reserved UTXO is about to reach the
+    /// node. Until then a reservation is only ours, and reconciliation leaves it alone.
+    pub fn mark_attempted(&mut self, outpoint: &TransactionOutpoint) {
+        if let Some(&id) = self.index.get(outpoint) {
+            if let Some(slot) = self.slots[id as usize].as_mut().filter(|s| s.state == UtxoState::Reserved) {
+                slot.attempted = true;
+            }
+        }
+    }
+
+    pub fn mark_submitted(&mut self, outpoint: &TransactionOutpoint, txid: TransactionId) {
+        if let Some(&id) = self.index.get(outpoint) {
+            let now = Instant::now();
+            self.set_state(id, UtxoState::Submitted, now);
+
+            let slot = self.slots[id as usize].as_mut().expect("indexed slot is occupied");
+            slot.spender = Some(txid);
+            slot.attempted = true;
+            if slot.local {
+                self.spent_local.push_back((id, now));
+            }
         }
     }
 
-    pub fn mark_spent(&mut self, outpoint: TransactionOutpoint) {
-        self.pending.remove(&outpoint);
-        self.spent.insert(outpoint);
+    /// Tracks a UTXO that a previous run spent in a transaction still in the mempool,
+    /// so it is not handed out again until the node confirms or drops the spend
+    pub fn restore_submitted(&mut self, outpoint: TransactionOutpoint, entry: CoreUtxoEntry, txid: Option<TransactionId>) {
+        if self.index.contains_key(&outpoint) {
+            return;
+        }
+        let id = self.insert_slot(outpoint, entry);
+        self.set_state(id, UtxoState::Submitted, Instant::now());
+        let slot = self.slots[id as usize].as_mut().expect("inserted slot is occupied");
+        slot.spender = txid;
+        slot.attempted = true;
+    }
+
+    /// Reserved and submitted outpoints, with the spending transaction once known
+    pub fn pending_spends(&self) -> impl Iterator<Item = (TransactionOutpoint, Option<TransactionId>)> + '_ {
+        self.pending_slots().map(|s| (s.outpoint, s.spender))
     }
 
-    pub fn release(&mut self, outpoint: &TransactionOutpoint) {
-        self.pending.remove(outpoint);
+    /// Every UTXO the node has reported to the pool, with its state and spender.
+    /// Outputs of our own transactions not yet reported are left out.
+    pub fn held(&self) -> impl Iterator<Item = (&TransactionOutpoint, &CoreUtxoEntry, UtxoState, Option<TransactionId>)> + '_ {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|s| !s.local)
+            .map(|s| (&s.outpoint, &s.entry, s.state, s.spender))
     }
 
-    pub fn prune_old_pending(&mut self, max_age_secs: u64) {
+    /// Gives up a reserved UTXO whose submission failed. With `requeue` it is handed
+    /// out again, for notification mode where no periodic refetch would bring it back
+    /// and a spend that did land still evicts it. Otherwise it is dropped and returns
+    /// with the next refresh if the node still reports it as unspent.
+    pub fn release(&mut self, outpoint: &TransactionOutpoint, requeue: bool) {
+        if let Some(&id) = self.index.get(outpoint) {
+            if self.is_in_state(id, UtxoState::Reserved) {
+                self.drop_reservation(id, requeue);
+            }
+        }
+    }
+
+    /// Removes an outpoint the node reported as spent, whatever its state. One we never
+    /// handed a spend of to the node is counted as invalidated, and a transaction still
+    /// being built or buffered from it will fail. Returns `None` if the pool did not
+    /// track it.
+    pub fn evict(&mut self, outpoint: &TransactionOutpoint) -> Option<Eviction> {
+        let id = *self.index.get(outpoint)?;
+        let slot = self.remove_slot(id);
+        Some(self.settle_removed(&slot))
+    }
+
+    /// Whether `outpoint` was invalidated recently, forgetting it once asked. Lets a
+    /// failed submission be blamed on the input that disappeared.
+    pub fn take_invalidated(&mut self, outpoint: &TransactionOutpoint) -> bool {
+        self.invalidated.remove(outpoint)
+    }
+
+    /// UTXOs removed by the node before we spent them, since startup
+    pub fn invalidations(&self) -> u64 {
+        self.invalidations
+    }
+
+    fn settle_removed(&mut self, slot: &Slot) -> Eviction {
+        if slot.attempted {
+            self.confirmed_spent += 1;
+            Eviction::Spent
+        } else {
+            self.invalidate(slot.outpoint);
+            Eviction::Invalidated(slot.state)
+        }
+    }
+
+    fn invalidate(&mut self, outpoint: TransactionOutpoint) {
         let now = Instant::now();
-        let max_age = std::time::Duration::from_secs(max_age_secs);
+        while let Some(&(old, at)) = self.invalidated_order.front() {
+            if now.duration_since(at) < INVALIDATED_RETENTION {
+                break;
+            }
+            self.invalidated_order.pop_front();
+            self.invalidated.remove(&old);
+        }
 
-        let old_count = self.pending.len();
-        self.pending.retain(|_, timestamp| {
-            now.duration_since(*timestamp) <= max_age
-        });
+        if self.invalidated.insert(outpoint) {
+            self.invalidated_order.push_back((outpoint, now));
+        }
+        self.invalidations += 1;
+    }
+
+    /// Pending outpoints whose spend went to the node and was not touched for `min_age`.
+    /// Reservations with no submit attempt are skipped, as a signed transaction for them
+    /// may still be waiting to go out, and so are our own outputs the node has not
+    /// reported, since they cannot be checked yet.
+    pub fn stale_pending(&self, min_age: Duration, out: &mut Vec<TransactionOutpoint>) {
+        out.extend(
+            self.pending_slots()
+                .filter(|s| s.attempted && !s.local && s.since.elapsed() >= min_age)
+                .map(|s| s.outpoint),
+        );
+    }
+
+    /// Settles a pending outpoint from snapshots of the mempool and the UTXO set.
+    /// `mempool_spender` is `Some` if a mempool transaction spends it. Spends in the
+    /// mempool stay locked as submitted, accepted ones are evicted, and lost ones
+    /// become available again. Returns `None` if the outpoint is no longer pending.
+    pub fn settle(
+        &mut self,
+        outpoint: &TransactionOutpoint,
+        mempool_spender: Option<Option<TransactionId>>,
+        unspent: bool,
+    ) -> Option<PendingFate> {
+        let id = *self.index.get(outpoint)?;
+        let slot = self.slots[id as usize].as_ref()?;
+        if slot.state == UtxoState::Available || !slot.attempted || slot.local {
+            return None;
+        }
+
+        let now = Instant::now();
+        match (mempool_spender, unspent) {
+            (Some(spender), _) => {
+                if slot.state == UtxoState::Reserved {
+                    self.set_state(id, UtxoState::Submitted, now);
+                }
+                if let Some(txid) = spender {
+                    self.slots[id as usize].as_mut().expect("indexed slot is occupied").spender = Some(txid);
+                }
+                Some(PendingFate::InMempool)
+            }
+            (None, false) => {
+                self.remove_slot(id);
+                self.confirmed_spent += 1;
+                Some(PendingFate::Accepted)
+            }
+            (None, true) => {
+                self.set_state(id, UtxoState::Available, now);
+                let slot = self.slots[id as usize].as_mut().expect("indexed slot is occupied");
+                slot.spender = None;
+                slot.attempted = false;
+                self.ready.push(id, &slot.entry);
+                Some(PendingFate::Lost)
+            }
+        }
+    }
+
+    /// Drops reservations older than `max_age_secs`, oldest first. With `requeue` the
+    /// UTXOs are handed out again instead, as in [`Self::release`].
+    pub fn prune_old_pending(&mut self, max_age_secs: u64, requeue: bool) {
+        let now = Instant::now();
+        let max_age = Duration::from_secs(max_age_secs);
+        let mut pruned = 0usize;
+
+        while let Some(&(id, reserved_at)) = self.reservations.front() {
+            if now.duration_since(reserved_at) <= max_age {
+                break;
+            }
+            self.reservations.pop_front();
+
+            let still_reserved = self.slots[id as usize]
+                .as_ref()
+                .is_some_and(|s| s.state == UtxoState::Reserved && s.since == reserved_at);
+            if still_reserved {
+                self.drop_reservation(id, requeue);
+                pruned += 1;
+            }
+        }
 
-        let pruned = old_count - self.pending.len();
         if pruned > 0 {
             debug!("Pruned {} old pending UTXOs", pruned);
         }
+
+        // The node may never report a local output that was created and spent
+        // between two of its updates, so spent ones are forgotten after a while
+        while let Some(&(id, spent_at)) = self.spent_local.front() {
+            if now.duration_since(spent_at) <= LOCAL_SPENT_RETENTION {
+                break;
+            }
+            self.spent_local.pop_front();
+
+            let still_spent = self.slots[id as usize]
+                .as_ref()
+                .is_some_and(|s| s.local && s.state == UtxoState::Submitted && s.since == spent_at);
+            if still_spent {
+                self.remove_slot(id);
+                self.confirmed_spent += 1;
+            }
+        }
+
+        // Expected outputs the node never reported belong to dropped transactions
+        while let Some(&(outpoint, expected_at)) = self.expected_order.front() {
+            if now.duration_since(expected_at) <= max_age {
+                break;
+            }
+            self.expected_order.pop_front();
+
+            if self.expected.get(&outpoint).is_some_and(|&(_, at)| at == expected_at) {
+                self.expected.remove(&outpoint);
+            }
+        }
+
+        self.compact_queues();
+    }
+
+    /// Drops stale slot ids once they outnumber live ones, so the queues stay
+    /// proportional to the pool instead of to the number of transactions sent
+    fn compact_queues(&mut self) {
+        if self.ready.len() > 2 * self.available_count() + QUEUE_SLACK {
+            let slots = &self.slots;
+            self.ready.retain(|id| {
+                slots[id as usize].as_ref().is_some_and(|s| s.state == UtxoState::Available)
+            });
+        }
+
+        if self.reservations.len() > 2 * self.pending_count() + QUEUE_SLACK {
+            let slots = &self.slots;
+            self.reservations.retain(|&(id, reserved_at)| {
+                slots[id as usize]
+                    .as_ref()
+                    .is_some_and(|s| s.state == UtxoState::Reserved && s.since == reserved_at)
+            });
+        }
+
+        if self.spent_local.len() > 2 * self.count(UtxoState::Submitted) + QUEUE_SLACK {
+            let slots = &self.slots;
+            self.spent_local.retain(|&(id, spent_at)| {
+                slots[id as usize]
+                    .as_ref()
+                    .is_some_and(|s| s.local && s.state == UtxoState::Submitted && s.since == spent_at)
+            });
+        }
+
+        if self.expected_order.len() > 2 * self.expected.len() + QUEUE_SLACK {
+            let expected = &self.expected;
+            self.expected_order.retain(|(outpoint, expected_at)| {
+                expected.get(outpoint).is_some_and(|(_, at)| at == expected_at)
+            });
+        }
+    }
+
+    /// Replaces this pool with `back`, built off-lock from a fresh fetch. Reserved and
+    /// submitted UTXOs carry over; ones the node no longer reports are evicted exactly
+    /// as a removal notification would. Local and expected outputs the node has not
+    /// reported yet carry over too. Returns the old pool so the caller can drop it
+    /// after unlocking.
+    pub fn swap_in(&mut self, mut back: UtxoManager) -> UtxoManager {
+        let mut reservations = Vec::with_capacity(self.pending_count());
+        let mut spent_local = Vec::new();
+        back.confirmed_spent = self.confirmed_spent;
+        back.invalidated = std::mem::take(&mut self.invalidated);
+        back.invalidated_order = std::mem::take(&mut self.invalidated_order);
+        back.invalidations = self.invalidations;
+
+        for slot in self.slots.iter().flatten() {
+            let id = match back.index.get(&slot.outpoint) {
+                Some(&id) => id,
+                None if slot.local => {
+                    let id = back.insert_slot(slot.outpoint, slot.entry.clone());
+                    back.slots[id as usize].as_mut().expect("inserted slot is occupied").local = true;
+                    id
+                }
+                None => {
+                    back.settle_removed(slot);
+                    continue;
+                }
+            };
+
+            // A requeued UTXO whose failed submit may still land keeps its attempt
+            back.slots[id as usize].as_mut().expect("carried slot is occupied").attempted = slot.attempted;
+            if slot.state == UtxoState::Available {
+                continue;
+            }
+
+            back.set_state(id, slot.state, slot.since);
+            back.slots[id as usize].as_mut().expect("carried slot is occupied").spender = slot.spender;
+            match slot.state {
+                UtxoState::Reserved => reservations.push((id, slot.since)),
+                UtxoState::Submitted if back.slots[id as usize].as_ref().is_some_and(|s| s.local) => {
+                    spent_local.push((id, slot.since));
+                }
+                _ => {}
+            }
+        }
+
+        back.expected = std::mem::take(&mut self.expected);
+        back.expected.retain(|outpoint, _| !back.index.contains_key(outpoint));
+        back.expected_order = std::mem::take(&mut self.expected_order);
+        back.cancelled = std::mem::take(&mut self.cancelled);
+
+        reservations.sort_by_key(|&(_, since)| since);
+        back.reservations = reservations.into();
+        spent_local.sort_by_key(|&(_, since)| since);
+        back.spent_local = spent_local.into();
+        back.last_refresh = Instant::now();
+
+        std::mem::replace(self, back)
+    }
+
+    pub fn count(&self, state: UtxoState) -> usize {
+        self.counts[state as usize]
     }
 
     pub fn available_count(&self) -> usize {
-        self.available.len().saturating_sub(self.index)
+        self.count(UtxoState::Available)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.count(UtxoState::Reserved)
     }
 
     pub fn total_balance(&self) -> u64 {
-        self.available.iter().map(|(_, e)| e.amount).sum()
+        self.available_balance
+    }
+
+    pub fn confirmed_spent(&self) -> u64 {
+        self.confirmed_spent
+    }
+
+    /// Appends `(amount, block DAA score)` of every available UTXO, for pool statistics
+    pub fn available_entries(&self, out: &mut Vec<(u64, u64)>) {
+        out.extend(
+            self.slots
+                .iter()
+                .flatten()
+                .filter(|s| s.state == UtxoState::Available)
+                .map(|s| (s.entry.amount, s.entry.block_daa_score)),
+        );
     }
-}
\ No newline at end of file
+
+    fn pending_slots(&self) -> impl Iterator<Item = &Slot> + '_ {
+        self.pending.iter().map(|&id| self.slots[id as usize].as_ref().expect("pending slot is occupied"))
+    }
+
+    fn drop_reservation(&mut self, id: u32, requeue: bool) {
+        if !requeue {
+            self.remove_slot(id);
+            return;
+        }
+        self.set_state(id, UtxoState::Available, Instant::now());
+        let slot = self.slots[id as usize].as_ref().expect("reserved slot is occupied");
+        self.ready.push(id, &slot.entry);
+    }
+
+    fn is_in_state(&self, id: u32, state: UtxoState) -> bool {
+        self.slots[id as usize].as_ref().is_some_and(|s| s.state == state)
+    }
+
+    fn set_state(&mut self, id: u32, state: UtxoState, now: Instant) {
+        let Some(slot) = self.slots[id as usize].as_mut() else {
+            return;
+        };
+
+        if slot.state == UtxoState::Available {
+            self.available_balance -= slot.entry.amount;
+        }
+        if state == UtxoState::Available {
+            self.available_balance += slot.entry.amount;
+            self.pending.remove(&id);
+        } else {
+            self.pending.insert(id);
+        }
+
+        self.counts[slot.state as usize] -= 1;
+        self.counts[state as usize] += 1;
+        slot.state = state;
+        slot.since = now;
+    }
+
+    fn remove_slot(&mut self, id: u32) -> Slot {
+        let slot = self.slots[id as usize].take().expect("removed slot is occupied");
+        if slot.state == UtxoState::Available {
+            self.available_balance -= slot.entry.amount;
+        } else {
+            self.pending.remove(&id);
+        }
+        self.counts[slot.state as usize] -= 1;
+        self.index.remove(&slot.outpoint);
+        self.vacant.push(id);
+        slot
+    }
+}
+
+/// One worker's slice of the UTXO pool. Locks are held only for bookkeeping, never across awaits.
+pub type UtxoShard = Arc<Mutex<UtxoManager>>;
+
+pub fn lock_shard(shard: &UtxoShard) -> MutexGuard<'_, UtxoManager> {
+    shard.lock().expect("UTXO shard lock poisoned")
+}
+
+/// The set of worker shards plus the authoritative record of which shard owns each
+/// outpoint. Only the refresher task mutates ownership, so shards stay disjoint.
+pub struct UtxoShards {
+    shards: Vec<UtxoShard>,
+    owners: HashMap<TransactionOutpoint, usize>,
+    maturing: SharedMaturity,
+    selection: Selection,
+    // Malformed entries skipped or patched up across all refreshes
+    malformed: usize,
+}
+
+impl UtxoShards {
+    /// Splits the initial UTXO set round-robin into `count` shards
+    pub fn new(set: UtxoSet, count: usize, selection: Selection) -> Self {
+        let UtxoSet { spendable: utxos, immature, .. } = set;
+
+        let mut maturing = MaturityQueue::default();
+        for (op, entry, ready_at) in immature {
+            maturing.push(op, entry, ready_at);
+        }
+
+        let mut owners = HashMap::with_capacity(utxos.len());
+        let mut parts: Vec<Vec<_>> = (0..count).map(|_| Vec::with_capacity(utxos.len() / count + 1)).collect();
+        for (i, utxo) in utxos.into_iter().enumerate() {
+            owners.insert(utxo.0, i % count);
+            parts[i % count].push(utxo);
+        }
+
+        Self {
+            shards: parts
+                .into_iter()
+                .map(|part| Arc::new(Mutex::new(UtxoManager::new(part, selection))))
+                .collect(),
+            owners,
+            maturing: Arc::new(Mutex::new(maturing)),
+            selection,
+            malformed: 0,
+        }
+    }
+
+    pub fn shards(&self) -> &[UtxoShard] {
+        &self.shards
+    }
+
+    pub fn maturity_queue(&self) -> SharedMaturity {
+        self.maturing.clone()
+    }
+
+    pub fn needs_refresh(&self, config: &Config) -> bool {
+        self.shards.iter().any(|s| lock_shard(s).needs_refresh(config))
+    }
+
+    /// Fetches the UTXO set into per-shard back buffers without holding any lock, then
+    /// swaps each buffer in. Known outpoints stay with their owner; new ones go to the
+    /// emptiest shards.
+    pub async fn refresh(&mut self, client: &GrpcClient, addresses: &[Address], config: &Config) -> Result<()> {
+        let UtxoSet { spendable: mut fresh, immature, malformed } = fetch_utxo_set(client, addresses, config).await?;
+
+        // Our own outputs stay with the shard that created them
+        let mut local = HashMap::new();
+        for (i, shard) in self.shards.iter().enumerate() {
+            local.extend(lock_shard(shard).local_outpoints().map(|op| (*op, i)));
+        }
+
+        // Immature UTXOs join the pool from the maturity queue once deep enough,
+        // except our own, which are usable as soon as the node reports them
+        {
+            let mut maturing = lock_maturity(&self.maturing);
+            maturing.clear();
+            for (op, entry, ready_at) in immature {
+                if local.contains_key(&op) {
+                    fresh.push((op, entry));
+                } else {
+                    maturing.push(op, entry, ready_at);
+                }
+            }
+        }
+        let fresh_count = fresh.len();
+        self.malformed += malformed.total();
+
+        let mut load: Vec<usize> = self.shards.iter().map(|s| lock_shard(s).available_count()).collect();
+        let mut parts: Vec<Vec<_>> = (0..self.shards.len()).map(|_| Vec::new()).collect();
+        let mut owners = HashMap::with_capacity(fresh_count);
+
+        for (op, entry) in fresh {
+            let shard = match self.owners.get(&op).or_else(|| local.get(&op)) {
+                Some(&shard) => shard,
+                None => {
+                    let shard = (0..load.len()).min_by_key(|&i| load[i]).unwrap_or(0);
+                    load[shard] += 1;
+                    shard
+                }
+            };
+            owners.insert(op, shard);
+            parts[shard].push((op, entry));
+        }
+
+        let old_count: usize = self.shards.iter().map(|s| lock_shard(s).available_count()).sum();
+        let mut totals = [0usize; STATE_COUNT];
+        let mut spent = 0u64;
+        let mut invalidated = 0u64;
+
+        for (shard, part) in self.shards.iter().zip(parts) {
+            let back = UtxoManager::with_utxos(part, self.selection);
+            let old = {
+                let mut front = lock_shard(shard);
+                let old = front.swap_in(back);
+                for (total, count) in totals.iter_mut().zip(front.counts) {
+                    *total += count;
+                }
+                spent += front.confirmed_spent();
+                invalidated += front.invalidations() - old.invalidations;
+                old
+            };
+            drop(old);
+        }
+
+        // Outpoints that vanished from the node are only kept by the shard that spent them
+        self.owners = owners;
+
+        if invalidated > 0 {
+            warn!("{} of our UTXOs disappeared from the node, removed by a reorg or a competing spend", invalidated);
+        }
+
+        info!(
+            "Refreshed UTXOs: {} available across {} shards (was {}), {} pending, {} submitted, {} spent, {} maturing, {} malformed",
+            totals[UtxoState::Available as usize],
+            self.shards.len(),
+            old_count,
+            totals[UtxoState::Reserved as usize],
+            totals[UtxoState::Submitted as usize],
+            spent,
+            lock_maturity(&self.maturing).len(),
+            self.malformed
+        );
+
+        Ok(())
+    }
+
+    /// Applies a UtxosChanged notification: removed outpoints are evicted from their
+    /// shard at once, added ones join the pool now or once they mature. Removals of
+    /// UTXOs we had not spent, from a reorg or a competing spend, are reported.
+    pub fn apply_changes(
+        &mut self,
+        added: &[RpcUtxosByAddressesEntry],
+        removed: &[RpcUtxosByAddressesEntry],
+        virtual_daa_score: u64,
+        config: &Config,
+    ) {
+        let (mut invalidated, mut in_flight) = (0usize, 0usize);
+        for entry in removed {
+            let op = TransactionOutpoint::from(entry.outpoint.clone());
+            if lock_maturity(&self.maturing).remove(&op) {
+                continue;
+            }
+            let state = match self.owners.remove(&op) {
+                Some(shard) => lock_shard(&self.shards[shard]).evict(&op),
+                None => self.shards.iter().find_map(|shard| lock_shard(shard).evict(&op)),
+            };
+            match state {
+                Some(Eviction::Invalidated(UtxoState::Reserved)) => {
+                    invalidated += 1;
+                    in_flight += 1;
+                }
+                Some(Eviction::Invalidated(_)) => invalidated += 1,
+                Some(Eviction::Spent) | None => {}
+            }
+        }
+        if invalidated > 0 {
+            warn!(
+                "{} of our UTXOs were removed by a reorg or a competing spend, {} of them reserved for unsent transactions",
+                invalidated, in_flight
+            );
+        }
+
+        let mut ready = Vec::new();
+        let mut claimed = 0usize;
+        let mut guards: Vec<_> = self.shards.iter().map(lock_shard).collect();
+        for entry in added {
+            let op = TransactionOutpoint::from(entry.outpoint.clone());
+            if self.owners.contains_key(&op) {
+                continue;
+            }
+
+            // Our own outputs go back to the shard that created them, whatever their depth
+            let utxo_entry = CoreUtxoEntry::from(entry.utxo_entry.clone());
+            if let Some(shard) = (0..guards.len()).find(|&i| guards[i].claim_reported(&op, &utxo_entry)) {
+                self.owners.insert(op, shard);
+                claimed += 1;
+                continue;
+            }
+
+            let ready_at = spendable_at(&utxo_entry, config);
+            if ready_at <= virtual_daa_score {
+                ready.push((op, utxo_entry));
+            } else {
+                lock_maturity(&self.maturing).push(op, utxo_entry, ready_at);
+            }
+        }
+
+        drop(guards);
+
+        debug!(
+            "UTXO changes: {} added ({} ours, {} spendable now), {} removed, {} maturing",
+            added.len(),
+            claimed,
+            ready.len(),
+            removed.len(),
+            lock_maturity(&self.maturing).len()
+        );
+        self.assign(ready);
+    }
+
+    /// Spreads UTXOs a previous run left in the mempool across the shards as submitted
+    pub fn restore_submitted(&mut self, pending: Vec<(TransactionOutpoint, CoreUtxoEntry, Option<TransactionId>)>) {
+        for (i, (op, entry, txid)) in pending.into_iter().enumerate() {
+            let shard = i % self.shards.len();
+            lock_shard(&self.shards[shard]).restore_submitted(op, entry, txid);
+            self.owners.insert(op, shard);
+        }
+    }
+
+    /// Checks pending spends older than the grace period against the node. The mempool
+    /// is read before the UTXO set, so a spend confirmed in between still shows up in
+    /// one of them and is never mistaken for lost.
+    pub async fn reconcile_pending(&mut self, client: &GrpcClient, addresses: &[Address], config: &Config) -> Result<()> {
+        let grace = Duration::from_secs(config.advanced.reconcile_grace_secs);
+
+        let mut stale = Vec::new();
+        let mut stale_shards = Vec::new();
+        for (i, shard) in self.shards.iter().enumerate() {
+            lock_shard(shard).stale_pending(grace, &mut stale);
+            stale_shards.resize(stale.len(), i);
+        }
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        let mempool = fetch_mempool_spends(client, addresses.to_vec()).await?;
+        let set = fetch_utxo_set(client, addresses, config).await?;
+        let unspent: HashSet<_> = set
+            .spendable
+            .iter()
+            .map(|(op, _)| *op)
+            .chain(set.immature.iter().map(|(op, _, _)| *op))
+            .collect();
+
+        let (mut in_mempool, mut accepted, mut lost) = (0usize, 0usize, 0usize);
+        for (op, shard) in stale.iter().zip(stale_shards) {
+            let fate = lock_shard(&self.shards[shard]).settle(op, mempool.get(op).copied(), unspent.contains(op));
+            match fate {
+                Some(PendingFate::InMempool) => in_mempool += 1,
+                Some(PendingFate::Accepted) => {
+                    self.owners.remove(op);
+                    accepted += 1;
+                }
+                Some(PendingFate::Lost) => lost += 1,
+                None => {}
+            }
+        }
+
+        info!(
+            "Reconciled {} pending spends: {} in mempool, {} accepted, {} lost and re-queued",
+            stale.len(),
+            in_mempool,
+            accepted,
+            lost
+        );
+        Ok(())
+    }
+
+    /// Moves matured UTXOs into the pool. Returns how many were added.
+    pub fn promote_matured(&mut self, virtual_daa_score: u64) -> usize {
+        let matured = lock_maturity(&self.maturing).pop_matured(virtual_daa_score);
+        let count = matured.len();
+        if count > 0 {
+            debug!("{} UTXOs matured at DAA {}", count, virtual_daa_score);
+        }
+        self.assign(matured);
+        count
+    }
+
+    /// Hands new UTXOs to the emptiest shards
+    fn assign(&mut self, utxos: Vec<(TransactionOutpoint, CoreUtxoEntry)>) {
+        if utxos.is_empty() {
+            return;
+        }
+
+        let mut guards: Vec<_> = self.shards.iter().map(lock_shard).collect();
+        for (op, entry) in utxos {
+            let shard = (0..guards.len())
+                .min_by_key(|&i| guards[i].available_count())
+                .unwrap_or(0);
+            if guards[shard].insert(op, entry) {
+                self.owners.insert(op, shard);
+            }
+        }
+    }
+
+    /// Moves UTXOs from the fullest shard into any shard below `low_water`.
+    /// Returns the number of UTXOs moved.
+    pub fn rebalance(&mut self, low_water: usize) -> usize {
+        if self.shards.len() < 2 {
+            return 0;
+        }
+
+        let mut guards: Vec<_> = self.shards.iter().map(lock_shard).collect();
+        let mut moved = 0usize;
+
+        for dry in 0..guards.len() {
+            let dry_count = guards[dry].available_count();
+            if dry_count >= low_water {
+                continue;
+            }
+
+            let donor = (0..guards.len())
+                .max_by_key(|&i| guards[i].available_count())
+                .unwrap_or(dry);
+            let donor_count = guards[donor].available_count();
+            if donor == dry || donor_count < low_water * 2 {
+                continue;
+            }
+
+            let mut utxos = Vec::new();
+            moved += guards[donor].take_remaining((donor_count - dry_count) / 2, &mut utxos);
+            for (op, _) in &utxos {
+                self.owners.insert(*op, dry);
+            }
+            guards[dry].adopt(utxos);
+        }
+
+        if moved > 0 {
+            debug!("Rebalanced {} UTXOs between shards", moved);
+        }
+
+        moved
+    }
+}
+
+/// Statistics over the available UTXOs of every shard
+pub fn pool_stats(shards: &[UtxoShard], dust_below: u64) -> PoolStats {
+    let mut entries = Vec::new();
+    for shard in shards {
+        lock_shard(shard).available_entries(&mut entries);
+    }
+    PoolStats::from_entries(entries, dust_below)
+}
+
+/// Keeps the shards up to date and balanced in the background, so submission never
+/// pauses for a `get_utxos_by_addresses` round-trip. With a subscription, changes are
+/// applied incrementally and the full set is refetched only after a reconnect;
+/// otherwise the set is polled every `refresh_interval_secs`.
+pub fn spawn_refresher(
+    mut shards: UtxoShards,
+    client: Arc<GrpcClient>,
+    addresses: Vec<Address>,
+    config: Arc<Config>,
+    low_water: usize,
+    telemetry: watch::Receiver<NodeTelemetry>,
+    mut subscription: Option<UtxoSubscription>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_millis(100));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let reconcile_every = config.advanced.reconcile_interval_secs;
+        let mut reconcile_ticker = tokio::time::interval(Duration::from_secs(reconcile_every.max(1)));
+        reconcile_ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let virtual_daa_score = telemetry.borrow().virtual_daa_score;
+
+                    let refetch = match subscription.as_mut() {
+                        Some(subscription) => subscription.check_reconnect().await,
+                        None => shards.needs_refresh(&config),
+                    };
+
+                    // Refetch the whole set if needed, otherwise top up dry shards
+                    if refetch {
+                        if let Err(e) = shards.refresh(&client, &addresses, &config).await {
+                            warn!("Failed to refresh UTXOs: {}", e);
+                        }
+                    } else {
+                        shards.promote_matured(virtual_daa_score);
+                        shards.rebalance(low_water);
+                    }
+                }
+
+                _ = reconcile_ticker.tick(), if reconcile_every > 0 => {
+                    if let Err(e) = shards.reconcile_pending(&client, &addresses, &config).await {
+                        warn!("Failed to reconcile pending spends: {}", e);
+                    }
+                }
+
+                Some(changes) = next_changes(subscription.as_ref()) => {
+                    let virtual_daa_score = telemetry.borrow().virtual_daa_score;
+                    shards.apply_changes(&changes.added, &changes.removed, virtual_daa_score, &config);
+                }
+            }
+        }
+    })
+}
+
+async fn next_changes(subscription: Option<&UtxoSubscription>) -> Option<UtxosChangedNotification> {
+    match subscription {
+        Some(subscription) => subscription.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SelectionStrategy;
+    use crate::testing::{outpoint, txid, utxo, wallet};
+    use kaspa_addresses::Version;
+
+    fn smallest_first() -> Selection {
+        Selection { strategy: SelectionStrategy::SmallestFirst, target: 0 }
+    }
+
+    /// UTXOs of the given amounts at `outpoint(1)`, `outpoint(2)` and so on
+    fn utxos(amounts: &[u64]) -> Vec<(TransactionOutpoint, CoreUtxoEntry)> {
+        amounts.iter().enumerate().map(|(i, &amount)| utxo(i as u8 + 1, amount)).collect()
+    }
+
+    fn pool(amounts: &[u64]) -> UtxoManager {
+        UtxoManager::with_utxos(utxos(amounts), smallest_first())
+    }
+
+    fn shards(amounts: &[u64], count: usize) -> UtxoShards {
+        UtxoShards::new(UtxoSet { spendable: utxos(amounts), ..Default::default() }, count, smallest_first())
+    }
+
+    fn coinbase(n: u8, amount: u64) -> (TransactionOutpoint, CoreUtxoEntry) {
+        let (op, entry) = utxo(n, amount);
+        (op, CoreUtxoEntry::new(entry.amount, entry.script_public_key, 0, true))
+    }
+
+    fn available(shards: &UtxoShards) -> Vec<usize> {
+        shards.shards().iter().map(|shard| lock_shard(shard).available_count()).collect()
+    }
+
+    fn reserve(pool: &mut UtxoManager, count: usize) -> Vec<TransactionOutpoint> {
+        let mut out = Vec::new();
+        pool.take_batch(count, &mut out);
+        out.into_iter().map(|(outpoint, _)| outpoint).collect()
+    }
+
+    #[test]
+    fn take_batch_reserves_in_selection_order() {
+        let mut pool = pool(&[300, 100, 200]);
+
+        assert_eq!(reserve(&mut pool, 2), vec![outpoint(2), outpoint(3)]);
+        assert_eq!(pool.available_count(), 1);
+        assert_eq!(pool.pending_count(), 2);
+        assert_eq!(pool.total_balance(), 300);
+        assert_eq!(reserve(&mut pool, 5), vec![outpoint(1)]);
+        assert!(reserve(&mut pool, 1).is_empty());
+    }
+
+    #[test]
+    fn removed_slots_are_reused() {
+        let mut pool = pool(&[100, 200]);
+        let mut out = Vec::new();
+        assert_eq!(pool.take_remaining(1, &mut out), 1);
+        assert_eq!(out[0].0, outpoint(2));
+
+        let (op, entry) = utxo(9, 50);
+        assert!(pool.insert(op, entry.clone()));
+        assert!(!pool.insert(op, entry));
+        assert_eq!(pool.slots.len(), 2);
+        assert_eq!(pool.available_count(), 2);
+        assert_eq!(pool.total_balance(), 150);
+        assert_eq!(reserve(&mut pool, 1), vec![outpoint(9)]);
+    }
+
+    #[test]
+    fn unreserve_hands_a_utxo_out_again() {
+        let mut pool = pool(&[100]);
+        let op = reserve(&mut pool, 1)[0];
+
+        pool.unreserve(&op);
+        assert_eq!(pool.available_count(), 1);
+        assert_eq!(pool.pending_count(), 0);
+        assert_eq!(reserve(&mut pool, 1), vec![op]);
+    }
+
+    #[test]
+    fn submitted_utxos_are_not_handed_out() {
+        let mut pool = pool(&[100, 200]);
+        let op = reserve(&mut pool, 1)[0];
+
+        pool.mark_submitted(&op, txid(0xaa));
+        assert_eq!(pool.count(UtxoState::Submitted), 1);
+        assert_eq!(pool.pending_count(), 0);
+        pool.unreserve(&op);
+        assert_eq!(reserve(&mut pool, 2), vec![outpoint(2)]);
+    }
+
+    #[test]
+    fn released_reservations_leave_the_pool() {
+        let mut pool = pool(&[100, 200]);
+        let op = reserve(&mut pool, 1)[0];
+
+        pool.release(&op, false);
+        assert_eq!(pool.pending_count(), 0);
+        assert_eq!(pool.available_count(), 1);
+        assert!(!pool.index.contains_key(&op));
+    }
+
+    #[test]
+    fn shards_split_the_set_round_robin() {
+        let shards = shards(&[100, 200, 300, 400, 500], 2);
+
+        assert_eq!(available(&shards), vec![3, 2]);
+        assert_eq!(shards.owners[&outpoint(1)], 0);
+        assert_eq!(shards.owners[&outpoint(2)], 1);
+        assert_eq!(lock_shard(&shards.shards()[0]).total_balance(), 900);
+    }
+
+    #[test]
+    fn rebalance_fills_dry_shards_from_the_fullest() {
+        let mut shards = shards(&[100, 200, 300, 400, 500, 600, 700, 800], 2);
+        lock_shard(&shards.shards()[1]).take_remaining(4, &mut Vec::new());
+        assert_eq!(available(&shards), vec![4, 0]);
+
+        // A donor must hold twice the low water mark
+        assert_eq!(shards.rebalance(3), 0);
+        assert_eq!(shards.rebalance(2), 2);
+        assert_eq!(available(&shards), vec![2, 2]);
+        assert_eq!(shards.owners[&outpoint(7)], 1);
+        assert_eq!(shards.owners[&outpoint(5)], 1);
+        assert_eq!(shards.rebalance(2), 0);
+    }
+
+    #[test]
+    fn swap_in_carries_pending_and_evicts_missing() {
+        let mut pool = pool(&[100, 200, 300]);
+        let ops = reserve(&mut pool, 2);
+        pool.mark_submitted(&ops[0], txid(0xcc));
+
+        // The node still reports the submitted UTXO and the available one, not the reservation
+        pool.swap_in(UtxoManager::with_utxos(vec![utxo(1, 100), utxo(3, 300)], smallest_first()));
+
+        assert_eq!(pool.count(UtxoState::Submitted), 1);
+        assert_eq!(pool.pending_count(), 0);
+        assert_eq!(pool.available_count(), 1);
+        assert_eq!(pool.invalidations(), 1);
+        assert_eq!(reserve(&mut pool, 2), vec![outpoint(3)]);
+    }
+
+    #[test]
+    fn pruning_compacts_stale_reservations() {
+        let mut pool = pool(&[100, 200]);
+        for _ in 0..QUEUE_SLACK {
+            for op in reserve(&mut pool, 2) {
+                pool.unreserve(&op);
+            }
+        }
+        let live = reserve(&mut pool, 1);
+        assert_eq!(pool.reservations.len(), 2 * QUEUE_SLACK + 1);
+
+        pool.prune_old_pending(3600, false);
+        assert_eq!(pool.reservations.len(), 1);
+        assert_eq!(pool.reservations[0].0, pool.index[&live[0]]);
+        assert_eq!(pool.available_count(), 1);
+    }
+
+    #[test]
+    fn released_reservations_can_be_requeued() {
+        let mut pool = pool(&[100, 200]);
+        let ops = reserve(&mut pool, 2);
+
+        pool.release(&ops[0], true);
+        assert_eq!(pool.pending_count(), 1);
+        assert_eq!(pool.available_count(), 1);
+        assert_eq!(reserve(&mut pool, 2), vec![ops[0]]);
+    }
+
+    #[test]
+    fn discarding_a_reserved_local_output_cancels_its_spend() {
+        let mut pool = pool(&[]);
+        let (op, entry) = utxo(1, 100);
+        assert!(pool.insert_local(op, entry));
+        assert_eq!(reserve(&mut pool, 1), vec![op]);
+
+        pool.discard_local(&op);
+        assert_eq!(pool.pending_count(), 0);
+        assert!(pool.take_cancelled(&op));
+        assert!(!pool.take_cancelled(&op));
+    }
+
+    #[test]
+    fn discarding_a_sent_local_output_leaves_it() {
+        let mut pool = pool(&[]);
+        let (op, entry) = utxo(1, 100);
+        pool.insert_local(op, entry);
+        reserve(&mut pool, 1);
+        pool.mark_attempted(&op);
+
+        pool.discard_local(&op);
+        assert_eq!(pool.pending_count(), 1);
+        assert!(!pool.take_cancelled(&op));
+    }
+
+    #[test]
+    fn pending_spends_track_reservations_and_submissions() {
+        let mut pool = pool(&[100, 200, 300]);
+        let ops = reserve(&mut pool, 2);
+        pool.mark_submitted(&ops[0], txid(0xaa));
+
+        let pending: Vec<_> = pool.pending_spends().collect();
+        assert_eq!(pending.len(), 2);
+        assert!(pending.contains(&(ops[0], Some(txid(0xaa)))));
+        assert!(pending.contains(&(ops[1], None)));
+
+        pool.unreserve(&ops[1]);
+        pool.evict(&ops[0]);
+        assert_eq!(pool.pending_spends().count(), 0);
+    }
+
+    #[test]
+    fn settle_skips_reservations_never_sent() {
+        let mut pool = pool(&[100]);
+        let op = reserve(&mut pool, 1)[0];
+
+        assert_eq!(pool.settle(&op, None, true), None);
+        assert_eq!(pool.settle(&op, None, false), None);
+        assert_eq!(pool.pending_count(), 1);
+    }
+
+    #[test]
+    fn settle_resolves_attempted_spends() {
+        let mut pool = pool(&[100, 200, 300]);
+        let ops = reserve(&mut pool, 3);
+        for op in &ops {
+            pool.mark_attempted(op);
+        }
+
+        assert_eq!(pool.settle(&ops[0], Some(Some(txid(0xbb))), true), Some(PendingFate::InMempool));
+        assert_eq!(pool.count(UtxoState::Submitted), 1);
+        assert_eq!(pool.settle(&ops[1], None, false), Some(PendingFate::Accepted));
+        assert_eq!(pool.confirmed_spent(), 1);
+        assert_eq!(pool.settle(&ops[2], None, true), Some(PendingFate::Lost));
+        assert_eq!(pool.available_count(), 1);
+
+        // A lost spend is ours again, so reconciliation no longer touches it
+        assert_eq!(pool.settle(&ops[2], None, true), None);
+    }
+
+    #[test]
+    fn stale_pending_lists_only_sent_spends() {
+        let mut pool = pool(&[100, 200]);
+        let ops = reserve(&mut pool, 2);
+        pool.mark_attempted(&ops[1]);
+
+        let mut stale = Vec::new();
+        pool.stale_pending(Duration::ZERO, &mut stale);
+        assert_eq!(stale, vec![ops[1]]);
+    }
+
+    #[test]
+    fn maturity_queue_releases_utxos_once_they_mature() {
+        let mut queue = MaturityQueue::default();
+        for (utxo, ready_at) in [(coinbase(1, 100), 10), (utxo(2, 200), 20), (coinbase(3, 300), 30)] {
+            queue.push(utxo.0, utxo.1, ready_at);
+        }
+        assert!(queue.remove(&outpoint(3)));
+        assert_eq!(queue.len(), 2);
+
+        assert!(queue.pop_matured(9).is_empty());
+        let matured: Vec<_> = queue.pop_matured(30).into_iter().map(|(op, _)| op).collect();
+        assert_eq!(matured, vec![outpoint(1), outpoint(2)]);
+        assert_eq!(queue.len(), 0);
+
+        let report = queue.report(30, 1000);
+        assert!(!report.is_empty());
+        assert_eq!((report.matured_coinbase, report.matured_coinbase_amount), (1, 100));
+    }
+
+    #[test]
+    fn maturity_report_buckets_coinbase_by_quarter() {
+        let mut queue = MaturityQueue::default();
+        for (n, ready_at) in [(1, 100), (2, 400), (3, 1000)] {
+            let (op, entry) = coinbase(n, 100);
+            queue.push(op, entry, ready_at);
+        }
+        let (op, entry) = utxo(4, 50);
+        queue.push(op, entry.clone(), 5);
+
+        // Queued again after a removal, it is still counted once
+        queue.remove(&op);
+        queue.push(op, entry, 8);
+        assert_eq!(queue.entries().count(), 4);
+
+        let report = queue.report(0, 1000);
+        assert_eq!(report.coinbase_by_quarter, [1, 1, 0, 1]);
+        assert_eq!((report.coinbase, report.coinbase_amount), (3, 300));
+        assert_eq!((report.regular, report.regular_amount), (1, 50));
+        assert_eq!(report.next_in, Some(5));
+    }
+
+    #[test]
+    fn matured_utxos_join_the_emptiest_shard() {
+        let (op, entry) = coinbase(9, 500);
+        let spendable = utxos(&[100, 200, 300]);
+        let set = UtxoSet { spendable, immature: vec![(op, entry, 1000)], ..Default::default() };
+        let mut shards = UtxoShards::new(set, 2, smallest_first());
+
+        assert_eq!(shards.promote_matured(999), 0);
+        assert_eq!(shards.promote_matured(1000), 1);
+        assert_eq!(available(&shards), vec![2, 2]);
+        assert_eq!(shards.owners[&outpoint(9)], 1);
+    }
+
+    #[test]
+    fn sorting_entries_skips_and_counts_malformed_ones() {
+        let wallet = wallet();
+        let ours = wallet.primary().address.clone();
+        let other = Address::new(ours.prefix, Version::PubKey, &[2; 32]);
+        let addresses = [ours.clone(), other.clone()];
+
+        let entry = |n: u8, reported: Option<&Address>, payee: Option<&Address>, daa: u64| {
+            let script = payee.map_or_else(|| ScriptPublicKey::from_vec(0, vec![0x51]), pay_to_address_script);
+            RpcUtxosByAddressesEntry {
+                address: reported.cloned(),
+                outpoint: outpoint(n).into(),
+                utxo_entry: CoreUtxoEntry::new(100 * n as u64, script, daa, false).into(),
+            }
+        };
+        let entries = vec![
+            entry(1, Some(&ours), Some(&ours), 0),
+            entry(2, None, Some(&ours), 0),
+            entry(3, Some(&other), Some(&ours), 0),
+            entry(4, Some(&ours), None, 0),
+            entry(1, Some(&ours), Some(&ours), 0),
+            entry(5, Some(&other), Some(&other), 95),
+        ];
+
+        let set = sort_utxo_entries(entries, &addresses, 100, &Config::default());
+        let spendable: Vec<_> = set.spendable.iter().map(|(op, _)| *op).collect();
+        assert_eq!(spendable, vec![outpoint(2), outpoint(1)]);
+        assert_eq!(set.immature.len(), 1);
+        assert_eq!((set.immature[0].0, set.immature[0].2), (outpoint(5), 105));
+
+        let malformed = set.malformed;
+        assert_eq!(
+            (malformed.unknown_script, malformed.wrong_address, malformed.duplicate, malformed.missing_address),
+            (1, 1, 1, 1)
+        );
+    }
+
+    #[test]
+    fn submitted_spends_are_tracked_until_evicted() {
+        let mut pool = pool(&[100]);
+        let op = reserve(&mut pool, 1)[0];
+        pool.mark_submitted(&op, txid(0xaa));
+
+        assert_eq!(pool.evict(&op), Some(Eviction::Spent));
+        assert_eq!(pool.confirmed_spent(), 1);
+        assert_eq!(pool.invalidations(), 0);
+        assert_eq!(pool.pending_spends().count(), 0);
+        assert_eq!(pool.evict(&op), None);
+    }
+
+    #[test]
+    fn evicting_an_unsent_utxo_counts_as_invalidated() {
+        let mut pool = pool(&[100, 200]);
+        let reserved = reserve(&mut pool, 1)[0];
+
+        assert_eq!(pool.evict(&reserved), Some(Eviction::Invalidated(UtxoState::Reserved)));
+        assert_eq!(pool.evict(&outpoint(2)), Some(Eviction::Invalidated(UtxoState::Available)));
+        assert_eq!(pool.invalidations(), 2);
+        assert!(pool.take_invalidated(&reserved));
+        assert!(!pool.take_invalidated(&reserved));
+    }
+
+    #[test]
+    fn evicting_an_attempted_reservation_counts_as_spent() {
+        let mut pool = pool(&[100]);
+        let op = reserve(&mut pool, 1)[0];
+
+        pool.mark_attempted(&op);
+        assert_eq!(pool.evict(&op), Some(Eviction::Spent));
+        assert_eq!(pool.invalidations(), 0);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::config::Config;
+use crate::error::{Result, TxGenError};
+use kaspa_addresses::{Address, Prefix, Version};
+use kaspa_bip32::{ChildNumber, DerivationPath, ExtendedPrivateKey};
+use kaspa_consensus_core::tx::ScriptPublicKey;
+use kaspa_grpc_client::GrpcClient;
+use kaspa_rpc_core::{api::rpc::RpcApi, model::GetUtxosByAddressesRequest};
+use kaspa_txscript::pay_to_address_script;
+use secp256k1::{Keypair, SecretKey, SECP256K1};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::{debug, info};
+
+/// Chain index of receive addresses under the account key
+const RECEIVE_CHAIN: u32 = 0;
+/// Chain index of change addresses under the account key
+const CHANGE_CHAIN: u32 = 1;
+
+/// BIP44 purpose and Kaspa coin type; accounts live at `<this>/<n>'`
+const KASPA_COIN_PATH: &str = "m/44'/111111'";
+
+/// Version prefixes of the extended private keys we accept
+const EXTENDED_KEY_PREFIXES: [&str; 4] = ["kprv", "ktrv", "xprv", "tprv"];
+
+/// Whether a secret is an extended private key rather than a raw hex key
+pub fn is_extended_key(secret: &str) -> bool {
+    EXTENDED_KEY_PREFIXES.iter().any(|prefix| secret.starts_with(prefix))
+}
+
+/// One spendable address with the key that signs for it
+pub struct WalletKey {
+    pub keypair: Keypair,
+    pub address: Address,
+    pub script_public_key: ScriptPublicKey,
+    /// Derivation path below the account key, for HD wallets
+    pub path: Option<String>,
+}
+
+/// The keys the generator spends from: a single raw key, or addresses derived from an
+/// extended private key on the standard Kaspa paths (`<account>/0/i` for receive,
+/// `<account>/1/i` for change)
+pub struct Wallet {
+    keys: Vec<WalletKey>,
+    by_script: HashMap<ScriptPublicKey, usize>,
+    master: Option<ExtendedPrivateKey<SecretKey>>,
+    account: Option<ExtendedPrivateKey<SecretKey>>,
+    prefix: Prefix,
+    // Round-robin position for spreading new outputs over the addresses
+    next_destination: AtomicUsize,
+}
+
+impl Wallet {
+    /// Builds the wallet from a raw 64-character hex key or an extended private key.
+    /// HD wallets start with the first `address_count` receive addresses.
+    pub fn from_secret(secret: &str, config: &Config) -> Result<Self> {
+        let prefix = config.network.network.prefix();
+        let mut wallet = Self {
+            keys: Vec::new(),
+            by_script: HashMap::new(),
+            master: None,
+            account: None,
+            prefix,
+            next_destination: AtomicUsize::new(0),
+        };
+
+        if !is_extended_key(secret) {
+            let secret_key = SecretKey::from_str(secret)
+                .map_err(|e| TxGenError::InvalidPrivateKey(format!("Invalid private key: {}", e)))?;
+            wallet.add(secret_key, None);
+            return Ok(wallet);
+        }
+
+        let master = ExtendedPrivateKey::<SecretKey>::from_str(secret)
+            .map_err(|e| TxGenError::InvalidPrivateKey(format!("Invalid extended private key: {}", e)))?;
+        let path = DerivationPath::from_str(&config.wallet.derivation_path).map_err(|e| {
+            TxGenError::Config(format!("Invalid derivation path {}: {}", config.wallet.derivation_path, e))
+        })?;
+        let account = master.clone().derive_path(&path).map_err(derivation_error)?;
+
+        for index in 0..config.wallet.address_count.max(1) {
+            let secret_key = derive(&account, RECEIVE_CHAIN, index)?;
+            wallet.add(secret_key, Some(format!("{}/{}", RECEIVE_CHAIN, index)));
+        }
+        wallet.master = Some(master);
+        wallet.account = Some(account);
+
+        Ok(wallet)
+    }
+
+    /// Scans the receive and change chains of an HD wallet for funded addresses, in
+    /// windows of `gap_limit`, stopping once `gap_limit` addresses in a row hold nothing
+    pub async fn discover(&mut self, client: &GrpcClient, config: &Config) -> Result<()> {
+        let Some(account) = self.account.clone() else {
+            return Ok(());
+        };
+        let gap_limit = config.wallet.gap_limit.max(1);
+        let before = self.keys.len();
+
+        for chain in [RECEIVE_CHAIN, CHANGE_CHAIN] {
+            let mut next = 0u32;
+            let mut last_used: Option<u32> = None;
+
+            loop {
+                let window = (next..next + gap_limit)
+                    .map(|index| {
+                        let secret_key = derive(&account, chain, index)?;
+                        Ok((index, secret_key, self.address_of(&secret_key)))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                let resp = client
+                    .get_utxos_by_addresses_call(None, GetUtxosByAddressesRequest {
+                        addresses: window.iter().map(|(_, _, address)| address.clone()).collect(),
+                    })
+                    .await?;
+                let funded: HashSet<Address> = resp.entries.into_iter().filter_map(|e| e.address).collect();
+
+                for (index, secret_key, address) in window {
+                    if funded.contains(&address) {
+                        last_used = Some(index);
+                        self.add(secret_key, Some(format!("{}/{}", chain, index)));
+                    }
+                }
+                next += gap_limit;
+
+                let unused_tail = next - last_used.map_or(0, |used| used + 1);
+                if unused_tail >= gap_limit {
+                    break;
+                }
+            }
+            debug!("Scanned chain {} up to index {}", chain, next);
+        }
+
+        info!(
+            "Wallet scan found {} more funded addresses, using {} in total",
+            self.keys.len() - before,
+            self.keys.len()
+        );
+        Ok(())
+    }
+
+    /// The first address, used to identify the wallet
+    pub fn primary(&self) -> &WalletKey {
+        &self.keys[0]
+    }
+
+    pub fn keys(&self) -> &[WalletKey] {
+        &self.keys
+    }
+
+    pub fn addresses(&self) -> Vec<Address> {
+        self.keys.iter().map(|key| key.address.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_hd(&self) -> bool {
+        self.account.is_some()
+    }
+
+    /// Derives recorded `chain/index` paths, e.g. from a snapshot, instead of scanning
+    pub fn restore_paths<'a>(&mut self, paths: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let Some(account) = self.account.clone() else {
+            return Ok(());
+        };
+
+        for path in paths {
+            let (chain, index) = path
+                .split_once('/')
+                .and_then(|(chain, index)| Some((chain.parse().ok()?, index.parse().ok()?)))
+                .ok_or_else(|| TxGenError::Parse(format!("Invalid address path {}", path)))?;
+            let secret_key = derive(&account, chain, index)?;
+            self.add(secret_key, Some(path.to_string()));
+        }
+        Ok(())
+    }
+
+    /// First receive address of account `n` on the standard Kaspa path, with that
+    /// account's derivation path. A generator run with the same extended key and
+    /// `derivation_path` set to the returned path spends from it.
+    pub fn account_address(&self, n: u32) -> Result<(Address, String)> {
+        let master = self.master.as_ref().ok_or_else(|| {
+            TxGenError::Config("Deriving worker accounts needs an extended private key".to_string())
+        })?;
+
+        let path = format!("{}/{}'", KASPA_COIN_PATH, n);
+        let parsed = DerivationPath::from_str(&path)
+            .map_err(|e| TxGenError::Config(format!("Invalid derivation path {}: {}", path, e)))?;
+        let account = master.clone().derive_path(&parsed).map_err(derivation_error)?;
+        let secret_key = derive(&account, RECEIVE_CHAIN, 0)?;
+        Ok((self.address_of(&secret_key), path))
+    }
+
+    /// The key that can spend outputs locked to `script`
+    pub fn key_for(&self, script: &ScriptPublicKey) -> Option<&WalletKey> {
+        self.by_script.get(script).map(|&i| &self.keys[i])
+    }
+
+    /// Next address to send a new output to, cycling through all of them
+    pub fn next_destination(&self) -> &WalletKey {
+        let i = self.next_destination.fetch_add(1, Ordering::Relaxed);
+        &self.keys[i % self.keys.len()]
+    }
+
+    fn address_of(&self, secret_key: &SecretKey) -> Address {
+        let keypair = Keypair::from_secret_key(SECP256K1, secret_key);
+        Address::new(self.prefix, Version::PubKey, &keypair.x_only_public_key().0.serialize())
+    }
+
+    fn add(&mut self, secret_key: SecretKey, path: Option<String>) {
+        let address = self.address_of(&secret_key);
+        let script_public_key = pay_to_address_script(&address);
+        if self.by_script.contains_key(&script_public_key) {
+            return;
+        }
+
+        self.by_script.insert(script_public_key.clone(), self.keys.len());
+        self.keys.push(WalletKey {
+            keypair: Keypair::from_secret_key(SECP256K1, &secret_key),
+            address,
+            script_public_key,
+            path,
+        });
+    }
+}
+
+fn derive(account: &ExtendedPrivateKey<SecretKey>, chain: u32, index: u32) -> Result<SecretKey> {
+    let chain = ChildNumber::new(chain, false).map_err(derivation_error)?;
+    let index = ChildNumber::new(index, false).map_err(derivation_error)?;
+    let key = account
+        .derive_child(chain)
+        .and_then(|key| key.derive_child(index))
+        .map_err(derivation_error)?;
+    Ok(*key.private_key())
+}
+
+fn derivation_error(e: kaspa_bip32::Error) -> TxGenError {
+    TxGenError::InvalidPrivateKey(format!("Key derivation failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::wallet;
+
+    fn hd_wallet(address_count: u32) -> Wallet {
+        let master = ExtendedPrivateKey::<SecretKey>::new([7u8; 64]).unwrap();
+        let mut config = Config::default();
+        config.wallet.address_count = address_count;
+        Wallet::from_secret(&master.to_string(kaspa_bip32::Prefix::XPRV), &config).unwrap()
+    }
+
+    #[test]
+    fn raw_keys_hold_a_single_address() {
+        let wallet = wallet();
+        assert_eq!(wallet.len(), 1);
+        assert!(!wallet.is_hd());
+        assert!(wallet.primary().path.is_none());
+        assert!(wallet.account_address(0).is_err());
+    }
+
+    #[test]
+    fn extended_keys_derive_the_receive_chain() {
+        let wallet = hd_wallet(3);
+        let paths: Vec<_> = wallet.keys().iter().map(|key| key.path.as_deref()).collect();
+        assert_eq!(paths, vec![Some("0/0"), Some("0/1"), Some("0/2")]);
+
+        let again = hd_wallet(3);
+        assert_eq!(wallet.addresses(), again.addresses());
+        for key in wallet.keys() {
+            assert_eq!(wallet.key_for(&key.script_public_key).unwrap().address, key.address);
+        }
+
+        // The default derivation path is account 0 on the standard path
+        let (address, path) = wallet.account_address(0).unwrap();
+        assert_eq!(address, wallet.primary().address);
+        assert_eq!(path, "m/44'/111111'/0'");
+        assert_ne!(wallet.account_address(1).unwrap().0, address);
+    }
+
+    #[test]
+    fn restored_paths_add_each_address_once() {
+        let mut wallet = hd_wallet(1);
+        wallet.restore_paths(["0/0", "1/4", "1/4"]).unwrap();
+        assert_eq!(wallet.len(), 2);
+        assert_eq!(wallet.keys()[1].path.as_deref(), Some("1/4"));
+        assert!(wallet.restore_paths(["1"]).is_err());
+    }
+
+    #[test]
+    fn destinations_cycle_through_every_key() {
+        let wallet = hd_wallet(2);
+        let destinations: Vec<_> = (0..4).map(|_| wallet.next_destination().address.clone()).collect();
+        assert_eq!(destinations[..2], wallet.addresses()[..]);
+        assert_eq!(destinations[2..], wallet.addresses()[..]);
+    }
+}